@@ -1,44 +1,292 @@
 use std::collections::{HashMap, HashSet};
 use crate::automata::dfa::builder::DFABuilder;
-use crate::LexError;
+use crate::{BuildError, LexError, Letter, Symbol};
 use crate::tree::LexTree;
-use super::super::{Automata, State};
+use super::super::{Automata, AutomataDiff, Inspect, Limits, State, TraceStep};
 use super::super::nfa::NFAutomata;
+use super::optimize::{DFAOptimizer, MinimizationAlgorithm, MinimizationLog};
 
-pub struct DFAutomata {
-    pub(super) transitions: HashMap<(State, char), State>,
+// the transitions leaving each state, the states are the indexes. They are keyed by symbol alone so
+// they can be looked up without owning the symbol
+pub(super) type Transitions<S> = Vec<HashMap<S, State>>;
+
+// groups the transitions by the state they leave
+pub(super) fn by_state<S: Letter>(transitions: HashMap<(State, S), State>, last_state: State) -> Transitions<S> {
+    let mut by_state = vec![HashMap::new(); last_state + 1];
+    for ((from, c), to) in transitions {
+        by_state[from].insert(c, to);
+    }
+    by_state
+}
+
+#[derive(Clone)]
+pub struct DFAutomata<S = char> {
+    pub(super) transitions: Transitions<S>,
     pub(super) acceptance_states: HashSet<State>,
-    pub(super) last_state: State
+    pub(super) last_state: State,
+    // the symbols the automata is defined over, even if some of them have no transitions. Kept
+    // sorted, as the transitions of every state are listed in this order
+    pub(super) alphabet: Vec<S>,
+    // the states of the NFA each state represents, when it was built through subset construction
+    pub(super) subsets: Option<Vec<Vec<State>>>,
+    // how the states were merged, when the automata was minimized
+    pub(super) minimization: Option<MinimizationLog<S>>,
 }
 
-impl DFAutomata {
-    pub(crate) fn new(transitions: HashMap<(State, char), State>, acceptance_states: HashSet<State>, last_state: State) -> DFAutomata {
-        DFAutomata { transitions, acceptance_states, last_state }
+impl<S: Letter> DFAutomata<S> {
+    pub(crate) fn new(transitions: HashMap<(State, S), State>, acceptance_states: HashSet<State>, last_state: State) -> DFAutomata<S> {
+        let alphabet: Vec<S> = transitions.keys().map(|(_, c)| c.clone()).collect();
+        let transitions = by_state(transitions, last_state);
+        DFAutomata { transitions, acceptance_states, last_state, alphabet: Vec::new(), subsets: None, minimization: None }
+            .with_alphabet(alphabet)
+    }
+
+    pub fn with_alphabet(mut self, alphabet: impl IntoIterator<Item=S>) -> DFAutomata<S> {
+        self.alphabet.extend(alphabet);
+        self.alphabet.sort();
+        self.alphabet.dedup();
+        self
+    }
+
+    // the subsets are expected to be sorted
+    pub(crate) fn with_subsets(mut self, subsets: Vec<Vec<State>>) -> DFAutomata<S> {
+        self.subsets = Some(subsets);
+        self
+    }
+
+    pub fn subsets(&self) -> Option<&[Vec<State>]> {
+        self.subsets.as_deref()
+    }
+
+    pub(crate) fn with_minimization(mut self, log: MinimizationLog<S>) -> DFAutomata<S> {
+        self.minimization = Some(log);
+        self
+    }
+
+    pub fn minimization(&self) -> Option<&MinimizationLog<S>> {
+        self.minimization.as_ref()
+    }
+
+    /// an automata accepting the reversed strings of this one. The acceptance states become the
+    /// start states, and the initial state becomes the only acceptance state.
+    pub fn reverse(&self) -> NFAutomata<S> {
+        let mut transitions = vec![Vec::new(); self.last_state + 1];
+
+        for (from, c, to) in self.edges() {
+            transitions[to].push((Symbol::Character(c.clone()), from));
+        }
+
+        NFAutomata::new(
+            transitions,
+            self.acceptance_states.clone(),
+            HashSet::from([0]),
+        )
+    }
+
+    /// minimizes the automata by determinizing its reverse twice
+    pub fn brzozowski(&self) -> DFAutomata<S> {
+        self.brzozowski_with_limits(&Limits::unlimited())
+            .expect("there are no limits")
+    }
+
+    /// determinizing the reverse may take exponentially many states, so it can be bounded
+    pub fn brzozowski_with_limits(&self, limits: &Limits) -> Result<DFAutomata<S>, BuildError> {
+        let mut automata = self.reverse()
+            .into_determinate_with_limits(false, limits)?
+            .reverse()
+            .into_determinate_with_limits(false, limits)?
+            .with_alphabet(self.alphabet.iter().cloned());
+
+        // the subsets are states of the reversed automata, which don't mean anything to the user
+        automata.subsets = None;
+        Ok(automata)
+    }
+
+    /// pairs the states reached by the same strings and reports what differs between them
+    pub fn diff(&self, other: &DFAutomata<S>) -> AutomataDiff<S> {
+        AutomataDiff::new(self, other)
+    }
+
+    /// whether the automata are the same up to renaming their states. Numbering both canonically
+    /// pairs the states reached by the same strings, so checking that pairing takes linear time
+    pub fn is_isomorphic(&self, other: &DFAutomata<S>) -> bool {
+        if self.last_state != other.last_state || self.edges().count() != other.edges().count()
+            || self.acceptance_states.len() != other.acceptance_states.len() {
+            return false;
+        }
+
+        let (left_ids, left_reachable) = self.canonical_ids();
+        let (right_ids, right_reachable) = other.canonical_ids();
+        if left_reachable != right_reachable {
+            return false;
+        }
+        if left_reachable <= self.last_state {
+            // the unreachable states aren't numbered canonically, so any pairing of them may work
+            return self.diff(other).is_isomorphic();
+        }
+
+        let mut right_states = vec![0; right_ids.len()];
+        right_ids.iter().enumerate().for_each(|(state, &id)| right_states[id] = state);
+        let pair = |state: State| right_states[left_ids[state]];
+
+        self.acceptance_states.iter().all(|&state| other.acceptance_states.contains(&pair(state)))
+            && self.edges().all(|(from, c, to)| other.movement(pair(from), c) == Some(pair(to)))
+    }
+
+    pub fn minimized(&self, algorithm: MinimizationAlgorithm) -> DFAutomata<S> {
+        self.minimize(algorithm, false)
+    }
+
+    /// minimizes the automata logging every split of the refinement, which takes quadratic time
+    pub fn minimized_with_steps(&self, algorithm: MinimizationAlgorithm) -> DFAutomata<S> {
+        self.minimize(algorithm, true)
+    }
+
+    fn minimize(&self, algorithm: MinimizationAlgorithm, log_steps: bool) -> DFAutomata<S> {
+        DFAOptimizer::optimize_with(
+            self.transitions.clone(),
+            self.acceptance_states.clone(),
+            self.last_state,
+            self.alphabet.clone(),
+            algorithm,
+            log_steps,
+        )
+    }
+
+    /// states that can't accept anything, because they only transition to themselves
+    pub fn is_sink(&self, state: State) -> bool {
+        !self.acceptance_states.contains(&state) && self.alphabet.iter()
+            .all(|c| self.movement(state, c) == Some(state))
+    }
+
+    /// adds a sink state receiving every missing transition, so every state has a transition for
+    /// every symbol of the alphabet
+    pub fn complete(&self) -> DFAutomata<S> {
+        let sink = self.last_state + 1;
+        let mut automata = self.clone();
+        automata.minimization = None;
+
+        automata.transitions.push(HashMap::new());
+        for edges in &mut automata.transitions {
+            for c in &self.alphabet {
+                edges.entry(c.clone()).or_insert(sink);
+            }
+        }
+
+        if automata.edges().any(|(_, _, to)| to == sink) {
+            automata.last_state = sink;
+            if let Some(subsets) = &mut automata.subsets {
+                subsets.push(Vec::new());
+            }
+        } else {
+            // it was already complete, so the sink isn't needed
+            automata.transitions.pop();
+        }
+
+        automata
+    }
+
+    /// an automata accepting the strings over the alphabet this one rejects. Strings with symbols
+    /// outside of the alphabet are still rejected.
+    pub fn complement(&self) -> DFAutomata<S> {
+        let mut automata = self.complete();
+        automata.acceptance_states = (0..=automata.last_state)
+            .filter(|state| !automata.acceptance_states.contains(state))
+            .collect();
+
+        automata
     }
 
-    fn movement(&self, state: State, c: char) -> Option<State> {
-        self.transitions.get(&(state, c)).copied()
+    pub(super) fn movement(&self, state: State, c: &S) -> Option<State> {
+        self.transitions.get(state)?.get(c).copied()
     }
 
+    // every transition as `(from, symbol, to)`, in no particular order
+    pub(super) fn edges(&self) -> impl Iterator<Item=(State, &S, State)> + '_ {
+        self.transitions.iter()
+            .enumerate()
+            .flat_map(|(from, edges)| edges.iter().map(move |(c, &to)| (from, c, to)))
+    }
+
+    /// whether the automata accepts the sequence of symbols
+    pub fn test_symbols(&self, input: impl IntoIterator<Item=S>) -> bool {
+        let final_state = input.into_iter()
+            .try_fold(0, |state, x| self.movement(state, &x));
+
+        final_state.is_some_and(|state| self.acceptance_states.contains(&state))
+    }
+}
+
+impl DFAutomata {
     pub fn unoptimized_from(node: &LexTree) -> DFAutomata {
         DFABuilder::build(node, false)
     }
+
+    /// the direct construction from the tree, unless the DFA gets bigger than the limits
+    pub fn build_with_limits(node: &LexTree, optimized: bool, limits: &Limits) -> Result<DFAutomata, BuildError> {
+        DFABuilder::build_with_limits(node, optimized, limits)
+    }
+
+    pub fn trace(&self, input: &str) -> Vec<TraceStep> {
+        let mut steps = vec![TraceStep { input: None, active_states: vec![0], transitions: Vec::new() }];
+        let mut state = 0;
+
+        for c in input.chars() {
+            match self.movement(state, &c) {
+                Some(next) => {
+                    steps.push(TraceStep {
+                        input: Some(c),
+                        active_states: vec![next],
+                        transitions: vec![(state, Symbol::Character(c), next)],
+                    });
+                    state = next;
+                },
+                None => {
+                    steps.push(TraceStep { input: Some(c), active_states: Vec::new(), transitions: Vec::new() });
+                    break;
+                }
+            }
+        }
+
+        steps
+    }
 }
 
 impl Automata for DFAutomata {
     fn test(&self, input: &str) -> bool {
-        let final_state = input.chars()
-            .try_fold(0, |state, x| self.movement(state, x));
+        self.test_symbols(input.chars())
+    }
+}
 
-        if let Some(final_state) = final_state {
-            return self.acceptance_states.contains(&final_state)
-        }
-        false
+impl<S: Letter> Inspect<S> for DFAutomata<S> {
+    fn state_count(&self) -> usize {
+        self.last_state + 1
+    }
+
+    fn start_states(&self) -> Vec<State> {
+        vec![0]
+    }
+
+    fn is_accepting(&self, state: State) -> bool {
+        self.acceptance_states.contains(&state)
+    }
+
+    fn transitions_from(&self, state: State) -> Vec<(Symbol<S>, State)> {
+        self.alphabet.iter()
+            .filter_map(|c| {
+                let to = self.movement(state, c)?;
+                Some((Symbol::Character(c.clone()), to))
+            })
+            .collect()
+    }
+
+    fn alphabet(&self) -> Vec<S> {
+        self.alphabet.clone()
     }
 }
 
-impl From<NFAutomata> for DFAutomata {
-    fn from(value: NFAutomata) -> Self {
+impl<S: Letter> From<NFAutomata<S>> for DFAutomata<S> {
+    fn from(value: NFAutomata<S>) -> Self {
         value.into_determinate(true)
     }
 }
@@ -57,3 +305,81 @@ impl From<&LexTree> for DFAutomata {
         DFABuilder::build(value, true)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trace() {
+        let automata = DFAutomata::try_from("ab*c").unwrap();
+        let after_a = automata.movement(0, &'a').unwrap();
+        let after_b = automata.movement(after_a, &'b').unwrap();
+
+        assert_eq!(
+            vec![
+                TraceStep { input: None, active_states: vec![0], transitions: vec![] },
+                TraceStep { input: Some('a'), active_states: vec![after_a], transitions: vec![(0, Symbol::Character('a'), after_a)] },
+                TraceStep { input: Some('b'), active_states: vec![after_b], transitions: vec![(after_a, Symbol::Character('b'), after_b)] },
+                TraceStep { input: Some('x'), active_states: vec![], transitions: vec![] },
+            ],
+            automata.trace("abxc")
+        );
+    }
+
+    #[test]
+    fn reverse() {
+        let automata = DFAutomata::try_from("ab*c|d").unwrap().reverse();
+
+        assert!(automata.test("cbba"));
+        assert!(automata.test("d"));
+        assert!(!automata.test("abc"));
+    }
+
+    #[test]
+    fn complement() {
+        let automata = DFAutomata::try_from("ab*").unwrap().with_alphabet(['c']);
+        let complete = automata.complete();
+        let complement = automata.complement();
+
+        assert_eq!(automata.last_state + 1, complete.last_state);
+        assert!(complete.is_sink(complete.last_state));
+        assert!(complete.test("abb"));
+        assert!(!complement.test("abb"));
+        assert!(complement.test("abc"));
+        assert!(complement.test(""));
+        assert!(!complement.test("abd"));
+    }
+
+    #[test]
+    fn brzozowski() {
+        let unoptimized = DFAutomata::unoptimized_from(&LexTree::try_from("(a|b)*abb(a|b)*").unwrap());
+        let automata = unoptimized.brzozowski();
+
+        assert_eq!(unoptimized.minimized(MinimizationAlgorithm::Hopcroft).last_state, automata.last_state);
+        assert!(automata.test("babba"));
+        assert!(!automata.test("abab"));
+    }
+
+    #[test]
+    fn symbols_looked_up_by_reference() {
+        use std::cell::Cell;
+
+        thread_local!(static CLONES: Cell<usize> = const { Cell::new(0) });
+        #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        struct Word(&'static str);
+        impl Clone for Word {
+            fn clone(&self) -> Word {
+                CLONES.with(|clones| clones.set(clones.get() + 1));
+                Word(self.0)
+            }
+        }
+
+        let automata = NFAutomata::sequence(["the", "lazy", "dog"].map(Word)).star().into_determinate(true);
+        let input: Vec<Word> = ["the", "lazy", "dog"].repeat(100).into_iter().map(Word).collect();
+
+        CLONES.with(|clones| clones.set(0));
+        assert!(automata.test_symbols(input));
+        assert_eq!(0, CLONES.with(Cell::get));
+    }
+}
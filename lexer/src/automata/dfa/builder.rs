@@ -1,83 +1,206 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Debug, Display, Formatter};
 use crate::automata::dfa::DFAutomata;
 use crate::automata::dfa::optimize::DFAOptimizer;
-use crate::automata::State;
+use crate::automata::{Limits, State};
+use crate::{BuildError, Visitable, Visitor};
+use crate::visitor::DebugWriter;
 use crate::operator::{BinaryOperator, UnaryOperator};
 use crate::symbols::Symbol;
 use crate::tree::LexTree;
 
-struct NodeValues {
+pub(super) struct NodeValues {
     first_positions: HashSet<usize>,
     last_positions: HashSet<usize>,
     nullable: bool,
 }
 
-pub struct DFABuilder {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeAnnotation {
+    pub nullable: bool,
+    pub first_positions: Vec<usize>,
+    pub last_positions: Vec<usize>,
+}
+
+impl From<&NodeValues> for NodeAnnotation {
+    fn from(value: &NodeValues) -> Self {
+        let mut first_positions: Vec<usize> = value.first_positions.iter().copied().collect();
+        first_positions.sort();
+        let mut last_positions: Vec<usize> = value.last_positions.iter().copied().collect();
+        last_positions.sort();
+
+        NodeAnnotation { nullable: value.nullable, first_positions, last_positions }
+    }
+}
+
+/// a LexTree with the values the direct DFA construction computes for each of its nodes
+pub struct AnnotatedLexTree<'a> {
+    pub node: &'a LexTree,
+    pub annotation: NodeAnnotation,
+    /// the position of the leaf, only chars have positions
+    pub position: Option<usize>,
+    pub children: Vec<AnnotatedLexTree<'a>>,
+}
+
+// like with LexTree, the subtrees are detached and dropped one at a time instead of recursing as
+// deep as the tree
+impl Drop for AnnotatedLexTree<'_> {
+    fn drop(&mut self) {
+        let mut pending = std::mem::take(&mut self.children);
+        while let Some(mut tree) = pending.pop() {
+            pending.append(&mut tree.children);
+        }
+    }
+}
+
+// written through a visitor so it works for trees of any depth. Only the operator or symbol of each
+// node is written, as its children already show the rest of its subtree
+impl Debug for AnnotatedLexTree<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut writer = DebugWriter::new(f.alternate());
+        self.accept(&mut writer);
+        f.write_str(&writer.finish())
+    }
+}
+
+impl<'a> Visitor<AnnotatedLexTree<'a>> for DebugWriter {
+    type Value = ();
+
+    fn enter(&mut self, tree: &AnnotatedLexTree<'a>) {
+        let node: &dyn Debug = match tree.node {
+            LexTree::Binary { value, .. } => value,
+            LexTree::Unary { value, .. } => value,
+            LexTree::Leaf { value } => value,
+        };
+        self.open("AnnotatedLexTree", &[("node", node), ("annotation", &tree.annotation), ("position", &tree.position)]);
+        self.field("children");
+        self.open_list();
+    }
+
+    fn before_child(&mut self, _parent: &AnnotatedLexTree<'a>, _index: usize, _siblings: &[()]) {
+        self.item();
+    }
+
+    fn leave(&mut self, _tree: &AnnotatedLexTree<'a>, _children: Vec<()>) {
+        self.close_list();
+        self.close();
+    }
+}
+
+impl Visitable for AnnotatedLexTree<'_> {
+    fn children(&self) -> Vec<&Self> {
+        self.children.iter().collect()
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Self> {
+        self.children.iter_mut().collect()
+    }
+}
+
+impl<'a> From<&'a LexTree> for AnnotatedLexTree<'a> {
+    fn from(value: &'a LexTree) -> Self {
+        DFABuilder::annotate(value).0
+    }
+}
+
+/// the followpos table of the direct DFA construction. The last position is the end marker `#`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FollowPositions {
+    /// the char at each position, along with its followpos. The end marker has no char
+    pub positions: Vec<(Option<char>, Vec<usize>)>,
+}
+
+impl From<&LexTree> for FollowPositions {
+    fn from(value: &LexTree) -> Self {
+        DFABuilder::annotate(value).1
+    }
+}
+
+impl Display for FollowPositions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "| position | symbol | followpos |\n|---|---|---|")?;
+
+        for (position, (c, follow_positions)) in self.positions.iter().enumerate() {
+            let c = c.map(|c| c.to_string()).unwrap_or_else(|| "#".to_string());
+            let follow_positions: Vec<String> = follow_positions.iter().map(|x| x.to_string()).collect();
+            let follow_positions = if follow_positions.is_empty() {
+                "-".to_string()
+            } else {
+                format!("{{{}}}", follow_positions.join(","))
+            };
+
+            write!(f, "\n| {position} | {c} | {follow_positions} |")?;
+        }
+
+        Ok(())
+    }
+}
+
+type PositionedAnnotation = (NodeAnnotation, Option<usize>);
+
+pub(super) struct DFABuilder {
     follow_positions: Vec<HashSet<usize>>,
     leaf_values: HashMap<char, HashSet<usize>>,
+    // the annotation and position of each node in pre order, only kept when annotating a tree.
+    // A node is given its slot when entered, and filled once its values are known
+    annotations: Option<Vec<Option<PositionedAnnotation>>>,
+    // the slots of the nodes being visited
+    open_slots: Vec<usize>,
 }
 impl DFABuilder {
     pub fn build(node: &LexTree, optimize: bool) -> DFAutomata {
+        DFABuilder::build_with_limits(node, optimize, &Limits::unlimited())
+            .expect("there are no limits")
+    }
+
+    pub fn build_with_limits(node: &LexTree, optimize: bool, limits: &Limits) -> Result<DFAutomata, BuildError> {
         let mut builder = DFABuilder {
             follow_positions: Vec::new(),
             leaf_values: HashMap::new(),
+            annotations: None,
+            open_slots: Vec::new(),
         };
 
-        // build the follow position table
-        let mut last_node = builder.initialize_values(node);
-
-        // add acceptance state
-        let acceptance_state = builder.follow_positions.len();
-        builder.follow_positions.push(HashSet::new());
-        last_node.last_positions
-            .into_iter()
-            .for_each(|x| {
-                builder
-                    .follow_positions[x]
-                    .insert(acceptance_state);
-            });
-
-        if last_node.nullable {
-            last_node.first_positions.insert(acceptance_state);
-        }
+        let (last_node, acceptance_state) = builder.follow_position_table(node);
 
         // build the automata;
         let mut acceptance_states = HashSet::new();
         let mut transitions: HashMap<(State, char), usize> = HashMap::new();
         let mut current_state_id = 0;
 
-        let mut known_states: Vec<HashSet<usize>> = vec![last_node.first_positions];
+        let chars = builder.position_chars();
+        let mut start_state: Vec<usize> = last_node.first_positions.into_iter().collect();
+        start_state.sort();
+        let mut state_ids: HashMap<Vec<usize>, State> = HashMap::from([(start_state.clone(), 0)]);
+        let mut known_states = vec![start_state];
 
         loop {
-            let current_state: HashSet<usize> = known_states[current_state_id].clone();
+            let current_state = &known_states[current_state_id];
 
             if current_state.contains(&acceptance_state) {
                 acceptance_states.insert(current_state_id);
             }
 
-            for (c, current_char_positions) in &builder.leaf_values {
-                let positions_with_cars: Vec<_> = current_state
-                    .intersection(current_char_positions)
-                    .collect();
-
-
-                if positions_with_cars.is_empty() {
-                    continue;
+            // the union of the followpos of the positions of each char
+            let mut movements: BTreeMap<char, HashSet<usize>> = BTreeMap::new();
+            for &position in current_state {
+                if let Some(c) = chars[position] {
+                    movements.entry(c).or_default().extend(&builder.follow_positions[position]);
                 }
+            }
 
-                let new_state = positions_with_cars
-                    .into_iter()
-                    .fold(HashSet::new(),
-                          |mut states, state| { states.extend(&builder.follow_positions[*state]); states });
+            for (c, new_state) in movements {
+                let mut new_state: Vec<usize> = new_state.into_iter().collect();
+                new_state.sort();
 
-                let to = known_states.iter()
-                    .position(|other| other == &new_state)
-                    .unwrap_or_else(|| {
-                        known_states.push(new_state);
-                        known_states.len() - 1
-                    });
+                let to = *state_ids.entry(new_state).or_insert_with_key(|new_state| {
+                    known_states.push(new_state.clone());
+                    known_states.len() - 1
+                });
+                limits.check_dfa_states(known_states.len())?;
 
-                transitions.insert((current_state_id, *c), to);
+                transitions.insert((current_state_id, c), to);
+                limits.check_transitions(transitions.len())?;
             }
 
             current_state_id += 1;
@@ -87,14 +210,100 @@ impl DFABuilder {
         }
 
         if optimize {
-            DFAOptimizer::optimize(
+            Ok(DFAOptimizer::optimize(
                 transitions,
                 acceptance_states,
                 current_state_id-1,
-                builder.leaf_values.into_keys().collect())
+                builder.leaf_values.into_keys().collect()))
         } else {
-            DFAutomata::new(transitions, acceptance_states, current_state_id-1)
+            Ok(DFAutomata::new(transitions, acceptance_states, current_state_id-1)
+                .with_alphabet(builder.leaf_values.into_keys()))
+        }
+    }
+
+    fn annotate(node: &LexTree) -> (AnnotatedLexTree<'_>, FollowPositions) {
+        let mut builder = DFABuilder {
+            follow_positions: Vec::new(),
+            leaf_values: HashMap::new(),
+            annotations: Some(Vec::new()),
+            open_slots: Vec::new(),
+        };
+        builder.follow_position_table(node);
+
+        let chars = builder.position_chars();
+
+        let follow_positions = FollowPositions {
+            positions: chars.into_iter()
+                .zip(&builder.follow_positions)
+                .map(|(c, follow_positions)| {
+                    let mut follow_positions: Vec<usize> = follow_positions.iter().copied().collect();
+                    follow_positions.sort();
+                    (c, follow_positions)
+                })
+                .collect()
+        };
+
+        let annotations = builder.annotations.take().expect("annotations were requested")
+            .into_iter()
+            .map(|annotation| annotation.expect("every node must have been annotated"));
+
+        (Self::attach_annotations(node, annotations), follow_positions)
+    }
+
+    // builds the annotated tree with a stack of the nodes being built, so it works for trees of any
+    // depth. The annotations were recorded in pre order, so each node takes the next one when entered
+    fn attach_annotations<'a>(node: &'a LexTree, mut annotations: impl Iterator<Item=PositionedAnnotation>) -> AnnotatedLexTree<'a> {
+        let mut enter = |node: &'a LexTree| {
+            let (annotation, position) = annotations.next().expect("every node must have been annotated");
+            (AnnotatedLexTree { node, annotation, position, children: Vec::new() }, node.children().into_iter())
+        };
+        let mut pending = vec![enter(node)];
+
+        loop {
+            let (_, children) = pending.last_mut().expect("the root is built last");
+
+            if let Some(child) = children.next() {
+                pending.push(enter(child));
+                continue;
+            }
+
+            let (tree, _) = pending.pop().expect("the node was just looked at");
+            match pending.last_mut() {
+                Some((parent, _)) => parent.children.push(tree),
+                None => return tree,
+            }
+        }
+    }
+
+    // the char at each position, the end marker has none
+    fn position_chars(&self) -> Vec<Option<char>> {
+        let mut chars = vec![None; self.follow_positions.len()];
+        for (c, positions) in &self.leaf_values {
+            positions.iter().for_each(|&position| chars[position] = Some(*c));
+        }
+        chars
+    }
+
+    // returns the values of the root along with the position of the end marker
+    fn follow_position_table(&mut self, node: &LexTree) -> (NodeValues, usize) {
+        let mut last_node = self.initialize_values(node);
+
+        // add acceptance state
+        let acceptance_state = self.follow_positions.len();
+        self.follow_positions.push(HashSet::new());
+        last_node.last_positions
+            .iter()
+            .for_each(|&x| {
+                self
+                    .follow_positions[x]
+                    .insert(acceptance_state);
+            });
+
+        if last_node.nullable {
+            last_node.first_positions.insert(acceptance_state);
         }
+
+        (last_node, acceptance_state)
     }
 
     fn connect_positions(&mut self, from_positions: &HashSet<usize>, follow_positions: &HashSet<usize>) {
@@ -107,6 +316,39 @@ impl DFABuilder {
     }
 
     fn initialize_values(&mut self, node: &LexTree) -> NodeValues {
+        node.accept(self)
+    }
+}
+
+// the values of every node, computed in post order from the ones of its children
+impl Visitor<LexTree> for DFABuilder {
+    type Value = NodeValues;
+
+    fn enter(&mut self, _node: &LexTree) {
+        if let Some(annotations) = &mut self.annotations {
+            self.open_slots.push(annotations.len());
+            annotations.push(None);
+        }
+    }
+
+    fn leave(&mut self, node: &LexTree, children: Vec<NodeValues>) -> NodeValues {
+        let node_values = self.node_values(node, children);
+        if let Some(annotations) = &mut self.annotations {
+            // a char was just given the last position
+            let position = matches!(node, LexTree::Leaf { value: Symbol::Character(_) })
+                .then(|| self.follow_positions.len() - 1);
+            let slot = self.open_slots.pop().expect("the node was entered");
+            annotations[slot] = Some((NodeAnnotation::from(&node_values), position));
+        }
+        node_values
+    }
+}
+
+impl DFABuilder {
+    fn node_values(&mut self, node: &LexTree, children: Vec<NodeValues>) -> NodeValues {
+        let mut children = children.into_iter();
+        let mut next = || children.next().expect("the children were visited");
+
         match node {
             LexTree::Leaf { value } => {
                 match value {
@@ -120,7 +362,7 @@ impl DFABuilder {
                         let position = self.follow_positions.len();
 
                         self.leaf_values.entry(*x)
-                            .or_insert_with(HashSet::new)
+                            .or_default()
                             .insert(position);
 
                         self.follow_positions.push(HashSet::new());
@@ -135,8 +377,8 @@ impl DFABuilder {
                     }
                 }
             },
-            LexTree::Unary { value, child } => {
-                let mut node_values = self.initialize_values(child);
+            LexTree::Unary { value, .. } => {
+                let mut node_values = next();
 
                 match value {
                     UnaryOperator::Kleene => {
@@ -159,9 +401,9 @@ impl DFABuilder {
                 }
                 node_values
             }
-            LexTree::Binary { value, left_child, right_child } => {
-                let mut left_child = self.initialize_values(left_child);
-                let right_child = self.initialize_values(right_child);
+            LexTree::Binary { value, .. } => {
+                let mut left_child = next();
+                let right_child = next();
 
                 match value {
                     BinaryOperator::Concat => {
@@ -195,3 +437,55 @@ impl DFABuilder {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follow_positions() {
+        let tree = LexTree::try_from("(a|b)*abb").unwrap();
+        let (annotated, follow_positions) = DFABuilder::annotate(&tree);
+
+        assert_eq!(NodeAnnotation { nullable: false, first_positions: vec![0, 1, 2], last_positions: vec![4] }, annotated.annotation);
+        assert_eq!(Some(4), annotated.children[1].position);
+        assert_eq!(vec![
+            (Some('a'), vec![0, 1, 2]),
+            (Some('b'), vec![0, 1, 2]),
+            (Some('a'), vec![3]),
+            (Some('b'), vec![4]),
+            (Some('b'), vec![5]),
+            (None, vec![]),
+        ], follow_positions.positions);
+    }
+
+    // cargo test --release -- --ignored --nocapture determinization_speed
+    #[test]
+    #[ignore]
+    fn determinization_speed() {
+        use crate::automata::Automata;
+        use crate::automata::nfa::NFAutomata;
+
+        // 500 pseudo random words of 8 letters
+        let mut seed: u64 = 7;
+        let keywords: Vec<String> = (0..500)
+            .map(|_| (0..8).map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (b'a' + (seed >> 59) as u8 % 26) as char
+            }).collect())
+            .collect();
+        let tree = LexTree::try_from(keywords.join("|").as_str()).unwrap();
+
+        let start = std::time::Instant::now();
+        let direct = DFABuilder::build(&tree, false);
+        let direct_time = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let subsets = NFAutomata::from(&tree).into_determinate(false);
+        let subsets_time = start.elapsed();
+
+        assert!(keywords.iter().all(|keyword| direct.test(keyword) && subsets.test(keyword)));
+        println!("direct {direct_time:?} ({} states), subset construction {subsets_time:?} ({} states)",
+                 direct.last_state + 1, subsets.last_state + 1);
+    }
+}
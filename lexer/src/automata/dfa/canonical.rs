@@ -0,0 +1,92 @@
+use std::collections::VecDeque;
+use super::automata::DFAutomata;
+use super::super::State;
+use crate::Letter;
+
+impl<S: Letter> DFAutomata<S> {
+    /// Renumbers the states in the order a breadth first search from the initial state finds them,
+    /// following the symbols in order. Automata accepting the same language through the same
+    /// transitions end up numbered the same way. The unreachable states go last, in their old order.
+    pub fn canonical(mut self) -> DFAutomata<S> {
+        let (new_ids, _) = self.canonical_ids();
+        let mut order = vec![0; new_ids.len()];
+        new_ids.iter().enumerate().for_each(|(state, &new_id)| order[new_id] = state);
+
+        let mut transitions = std::mem::take(&mut self.transitions);
+        self.transitions = order.iter()
+            .map(|&state| std::mem::take(&mut transitions[state]).into_iter().map(|(c, to)| (c, new_ids[to])).collect())
+            .collect();
+        self.acceptance_states = self.acceptance_states.into_iter().map(|state| new_ids[state]).collect();
+        if let Some(subsets) = &mut self.subsets {
+            *subsets = order.iter().map(|&state| std::mem::take(&mut subsets[state])).collect();
+        }
+        if let Some(log) = &mut self.minimization {
+            log.merged_states = order.iter().map(|&state| std::mem::take(&mut log.merged_states[state])).collect();
+        }
+
+        self
+    }
+
+    // the canonical id of every state, along with how many states are reachable
+    pub(super) fn canonical_ids(&self) -> (Vec<State>, usize) {
+        let mut new_ids: Vec<Option<State>> = vec![None; self.last_state + 1];
+        let mut pending = VecDeque::from([0]);
+        let mut reachable = 1;
+        new_ids[0] = Some(0);
+
+        while let Some(state) = pending.pop_front() {
+            for c in &self.alphabet {
+                if let Some(to) = self.movement(state, c) {
+                    if new_ids[to].is_none() {
+                        new_ids[to] = Some(reachable);
+                        reachable += 1;
+                        pending.push_back(to);
+                    }
+                }
+            }
+        }
+
+        let mut next_id = reachable;
+        let new_ids = new_ids.into_iter()
+            .map(|id| id.unwrap_or_else(|| {
+                next_id += 1;
+                next_id - 1
+            }))
+            .collect();
+        (new_ids, reachable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::DFAVisualizer;
+    use crate::mermaid_graph::MermaidGraph;
+
+    #[test]
+    fn canonical() {
+        let automata = DFAutomata::try_from("(a|b)*abb").unwrap();
+
+        let mut transitions: Vec<((State, char), State)> = automata.edges().map(|(from, &c, to)| ((from, c), to)).collect();
+        transitions.sort();
+        assert_eq!(vec![
+            ((0, 'a'), 1), ((0, 'b'), 0),
+            ((1, 'a'), 1), ((1, 'b'), 2),
+            ((2, 'a'), 1), ((2, 'b'), 3),
+            ((3, 'a'), 1), ((3, 'b'), 0),
+        ], transitions);
+        // the direct construction already gives the minimal automata
+        assert_eq!(vec![vec![0], vec![1], vec![2], vec![3]], automata.minimization().unwrap().merged_states);
+    }
+
+    #[test]
+    fn reproducible() {
+        let mermaid = || {
+            let automata = DFAutomata::try_from("(a|b|c)*a(b|c)?(ab)*").unwrap();
+            DFAVisualizer::new(&automata).get_mermaid_content().to_string()
+        };
+
+        let first = mermaid();
+        assert!((0..10).all(|_| mermaid() == first));
+    }
+}
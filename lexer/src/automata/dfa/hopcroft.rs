@@ -0,0 +1,189 @@
+use std::collections::HashSet;
+use crate::Letter;
+use super::optimize::{sorted, DFAOptimizer, RefinementStep};
+use super::super::State;
+
+// Keeps the states of every block contiguous in `elements`, so a block can be split in place and
+// the block of any state can be found in constant time.
+struct Partition {
+    elements: Vec<State>,
+    location: Vec<usize>,
+    block_of: Vec<usize>,
+    // the range of `elements` of each block
+    blocks: Vec<(usize, usize)>,
+    // the amount of states marked at the start of each block
+    marked: Vec<usize>,
+}
+
+impl Partition {
+    fn new(states: usize, initial_blocks: &[Vec<State>]) -> Partition {
+        let mut partition = Partition {
+            elements: Vec::with_capacity(states),
+            location: vec![0; states],
+            block_of: vec![0; states],
+            blocks: Vec::new(),
+            marked: Vec::new(),
+        };
+
+        for block in initial_blocks.iter().filter(|block| !block.is_empty()) {
+            let start = partition.elements.len();
+            for &state in block {
+                partition.location[state] = partition.elements.len();
+                partition.block_of[state] = partition.blocks.len();
+                partition.elements.push(state);
+            }
+            partition.blocks.push((start, partition.elements.len()));
+            partition.marked.push(0);
+        }
+
+        partition
+    }
+
+    fn states(&self, block: usize) -> &[State] {
+        let (start, end) = self.blocks[block];
+        &self.elements[start..end]
+    }
+
+    fn mark(&mut self, state: State) {
+        let block = self.block_of[state];
+        let (start, _) = self.blocks[block];
+        let first_unmarked = start + self.marked[block];
+        let location = self.location[state];
+
+        if location < first_unmarked {
+            return; // it was already marked
+        }
+
+        // swap the state with the first unmarked one
+        let other = self.elements[first_unmarked];
+        self.elements.swap(location, first_unmarked);
+        self.location[state] = first_unmarked;
+        self.location[other] = location;
+        self.marked[block] += 1;
+    }
+
+    // splits the marked states of the block into a new block, returning it if the block was split
+    fn split(&mut self, block: usize) -> Option<usize> {
+        let (start, end) = self.blocks[block];
+        let marked = std::mem::replace(&mut self.marked[block], 0);
+
+        if marked == 0 || marked == end - start {
+            return None;
+        }
+
+        let new_block = self.blocks.len();
+        self.blocks[block] = (start + marked, end);
+        self.blocks.push((start, start + marked));
+        self.marked.push(0);
+        for &state in &self.elements[start..start + marked] {
+            self.block_of[state] = new_block;
+        }
+
+        Some(new_block)
+    }
+}
+
+impl<S: Letter> DFAOptimizer<S> {
+    // Hopcroft's worklist algorithm. Missing transitions go to an extra sink state, whose block ends up
+    // being the dead partition.
+    pub(super) fn hopcroft_partitions(&mut self, last_state: State) {
+        let sink = last_state + 1;
+        let states = sink + 1;
+        let symbols = self.alphabet.len();
+
+        // the states reaching each state through each symbol
+        let mut inverse: Vec<Vec<State>> = vec![Vec::new(); states * symbols];
+        for from in 0..states {
+            for (symbol, c) in self.alphabet.iter().enumerate() {
+                let to = self.old_movement(from, c).unwrap_or(sink);
+                inverse[to * symbols + symbol].push(from);
+            }
+        }
+
+        let accepting: Vec<State> = (0..states).filter(|x| self.old_acceptance_states.contains(x)).collect();
+        let rejecting: Vec<State> = (0..states).filter(|x| !self.old_acceptance_states.contains(x)).collect();
+        let mut partition = Partition::new(states, &[rejecting, accepting]);
+
+        // start with the smallest of the initial blocks for every symbol
+        let smallest = (0..partition.blocks.len())
+            .min_by_key(|&block| partition.states(block).len())
+            .expect("there is at least one block");
+        let mut worklist: Vec<(usize, usize)> = (0..symbols).map(|symbol| (smallest, symbol)).collect();
+        let mut in_worklist: HashSet<(usize, usize)> = worklist.iter().copied().collect();
+
+        let mut round = 0;
+        while let Some((splitter, symbol)) = worklist.pop() {
+            in_worklist.remove(&(splitter, symbol));
+            round += 1;
+
+            let mut touched_blocks = Vec::new();
+            let splitter_states = partition.states(splitter).to_vec();
+            for to in splitter_states {
+                for &from in &inverse[to * symbols + symbol] {
+                    if partition.marked[partition.block_of[from]] == 0 {
+                        touched_blocks.push(partition.block_of[from]);
+                    }
+                    partition.mark(from);
+                }
+            }
+
+            for block in touched_blocks {
+                // copying the whole group is what makes logging quadratic
+                let group: Option<Vec<State>> = self.log_steps.then(|| partition.states(block).to_vec());
+
+                let Some(new_block) = partition.split(block) else {
+                    continue;
+                };
+
+                for other_symbol in 0..symbols {
+                    let smaller = if in_worklist.contains(&(block, other_symbol))
+                        || partition.states(new_block).len() <= partition.states(block).len() {
+                        new_block
+                    } else {
+                        block
+                    };
+                    if in_worklist.insert((smaller, other_symbol)) {
+                        worklist.push((smaller, other_symbol));
+                    }
+                }
+
+                if let Some(group) = group {
+                    self.log_split(round, symbol, sink, group, &[partition.states(new_block), partition.states(block)]);
+                }
+            }
+        }
+
+        self.partitions = (0..partition.blocks.len())
+            .map(|block| partition.states(block).iter().copied().collect::<HashSet<State>>())
+            .collect();
+
+        // everything equivalent to the sink is dead
+        let dead_block = partition.block_of[sink];
+        let mut dead_states = self.partitions.remove(dead_block);
+        dead_states.remove(&sink);
+        self.log.dead_states = if dead_states.is_empty() { None } else { Some(sorted(&dead_states)) };
+    }
+
+    fn log_split(&mut self, round: usize, symbol: usize, sink: State, group: Vec<State>, split_into: &[&[State]]) {
+        let without_sink = |states: &[State]| -> Vec<State> {
+            let mut states: Vec<State> = states.iter().copied().filter(|&state| state != sink).collect();
+            states.sort();
+            states
+        };
+
+        let split_into: Vec<Vec<State>> = split_into.iter()
+            .map(|states| without_sink(states))
+            .filter(|states| !states.is_empty())
+            .collect();
+
+        // separating the sink alone from a group isn't interesting to show
+        if split_into.len() > 1 {
+            self.log.steps.push(RefinementStep {
+                round,
+                symbol: self.alphabet[symbol].clone(),
+                group: without_sink(&group),
+                split_into,
+            });
+        }
+    }
+}
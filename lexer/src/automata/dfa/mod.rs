@@ -2,7 +2,21 @@ mod automata;
 mod visualizer;
 mod builder;
 mod optimize;
+mod hopcroft;
+mod report;
+mod product;
+mod refinement_visualizer;
+mod table;
+mod utf8;
+mod canonical;
 
 pub use automata::DFAutomata;
 pub use visualizer::DFAVisualizer;
+pub use report::MatchReport;
+pub use product::BooleanOperation;
+pub use builder::{AnnotatedLexTree, NodeAnnotation, FollowPositions};
+pub use optimize::{MinimizationAlgorithm, MinimizationLog, RefinementStep};
+pub use refinement_visualizer::RefinementVisualizer;
+pub use table::{DenseDFA, CombDFA, CharClasses, DEAD};
+pub use utf8::ByteDFA;
 pub(super) use optimize::DFAOptimizer;
\ No newline at end of file
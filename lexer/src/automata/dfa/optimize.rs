@@ -1,42 +1,109 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use crate::automata::dfa::DFAutomata;
+use super::automata::{by_state, Transitions};
 use crate::automata::State;
+use crate::Letter;
 
-pub struct DFAOptimizer{
-    old_transitions: HashMap<(State, char), State>,
-    partitions: Vec<HashSet<State>>,
-    alphabet: Vec<char>,
-    old_acceptance_states: HashSet<State>,
+pub struct DFAOptimizer<S> {
+    pub(super) old_transitions: Transitions<S>,
+    pub(super) partitions: Vec<HashSet<State>>,
+    pub(super) alphabet: Vec<S>,
+    pub(super) old_acceptance_states: HashSet<State>,
+    pub(super) log: MinimizationLog<S>,
+    // whether every split is added to the log
+    pub(super) log_steps: bool,
 }
 
-impl DFAOptimizer {
-    pub fn optimize(transitions: HashMap<(State, char), State>, old_acceptance_states: HashSet<State>, last_state: State, alphabet: Vec<char>) -> DFAutomata {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MinimizationAlgorithm {
+    /// refines every partition on every symbol until nothing changes
+    Moore,
+    /// only refines the partitions that may be split by a worklist of splitters
+    #[default]
+    Hopcroft,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinimizationLog<S = char> {
+    pub initial_partitions: Vec<Vec<State>>,
+    /// only recorded when asked for, as copying every group that is split takes quadratic time
+    pub steps: Vec<RefinementStep<S>>,
+    /// the partition that was removed because it could never reach an accepting state
+    pub dead_states: Option<Vec<State>>,
+    /// the original states merged into each state of the minimized automata
+    pub merged_states: Vec<Vec<State>>,
+}
+
+impl<S> Default for MinimizationLog<S> {
+    fn default() -> Self {
+        MinimizationLog { initial_partitions: Vec::new(), steps: Vec::new(), dead_states: None, merged_states: Vec::new() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefinementStep<S = char> {
+    /// when the split happened, starting from 1. For Moore it is the pass over the whole alphabet,
+    /// for Hopcroft the splitter taken from the worklist
+    pub round: usize,
+    pub symbol: S,
+    pub group: Vec<State>,
+    pub split_into: Vec<Vec<State>>,
+}
+
+pub(super) fn sorted(states: &HashSet<State>) -> Vec<State> {
+    let mut states: Vec<State> = states.iter().copied().collect();
+    states.sort();
+    states
+}
+
+impl<S: Letter> DFAOptimizer<S> {
+    pub fn optimize(transitions: HashMap<(State, S), State>, old_acceptance_states: HashSet<State>, last_state: State, alphabet: Vec<S>) -> DFAutomata<S> {
+        DFAOptimizer::optimize_with(by_state(transitions, last_state), old_acceptance_states, last_state, alphabet, MinimizationAlgorithm::default(), false)
+    }
+
+    pub fn optimize_with(transitions: Transitions<S>, old_acceptance_states: HashSet<State>, last_state: State, mut alphabet: Vec<S>, algorithm: MinimizationAlgorithm, log_steps: bool) -> DFAutomata<S> {
+        // the order of the refinements depends on the order of the alphabet. Callers collecting it from
+        // their transitions repeat a symbol once per transition, which Moore would refine on again on
+        // every pass and Hopcroft would add as a splitter again for every partition
+        alphabet.sort();
+        alphabet.dedup();
+
         if old_acceptance_states.is_empty() {
             // if the expression accepts nothing then let's just return an automata that does that
-            return DFAutomata { transitions: HashMap::new(), acceptance_states: HashSet::new(), last_state: 0 }
+            let all_states: Vec<State> = (0..=last_state).collect();
+            let log = MinimizationLog {
+                initial_partitions: vec![all_states.clone()],
+                merged_states: vec![all_states],
+                ..MinimizationLog::default()
+            };
+            return DFAutomata::new(HashMap::new(), HashSet::new(), 0)
+                .with_alphabet(alphabet)
+                .with_minimization(log)
         }
 
-        let mut optimizer = DFAOptimizer::new(transitions, old_acceptance_states, alphabet, last_state);
+        let mut optimizer = DFAOptimizer::new(transitions, old_acceptance_states, alphabet, last_state, algorithm, log_steps);
 
         let (new_transitions, new_acceptance_states) = optimizer.new_transitions();
+        optimizer.log.merged_states = optimizer.partitions.iter().map(sorted).collect();
 
-        DFAutomata { transitions: new_transitions,
-            acceptance_states: new_acceptance_states,
-            last_state: optimizer.partitions.len()-1
-        }
+        DFAutomata::new(new_transitions, new_acceptance_states, optimizer.partitions.len()-1)
+            .with_alphabet(optimizer.alphabet)
+            .with_minimization(optimizer.log)
+            .canonical()
     }
 
-    fn new(old_transitions: HashMap<(State, char), State>, acceptance_states: HashSet<State>, alphabet: Vec<char>, last_state: State) -> DFAOptimizer {
+    fn new(old_transitions: Transitions<S>, acceptance_states: HashSet<State>, alphabet: Vec<S>, last_state: State, algorithm: MinimizationAlgorithm, log_steps: bool) -> DFAOptimizer<S> {
         let mut optimizer = if acceptance_states.len()-1 == last_state {
             DFAOptimizer {
                 old_transitions,
                 partitions: vec![acceptance_states.clone()],
                 alphabet,
-                old_acceptance_states: acceptance_states
+                old_acceptance_states: acceptance_states,
+                log: MinimizationLog::default(),
+                log_steps,
             }
         } else {
             let other_states: HashSet<State> = (0..=last_state)
-                .into_iter()
                 .filter(|x| !acceptance_states.contains(x))
                 .collect();
 
@@ -44,25 +111,36 @@ impl DFAOptimizer {
                 old_transitions,
                 partitions: vec![other_states, acceptance_states.clone()],
                 alphabet,
-                old_acceptance_states: acceptance_states
+                old_acceptance_states: acceptance_states,
+                log: MinimizationLog::default(),
+                log_steps,
             }
         };
 
-        optimizer.fix_partitions();
-        optimizer.remove_dead_partitions();
+        optimizer.log.initial_partitions = optimizer.partitions.iter().map(sorted).collect();
+        match algorithm {
+            MinimizationAlgorithm::Moore => {
+                optimizer.fix_partitions();
+                optimizer.log.dead_states = optimizer.remove_dead_partitions()
+                    .map(|partition| sorted(&partition));
+            },
+            MinimizationAlgorithm::Hopcroft => optimizer.hopcroft_partitions(last_state),
+        }
         optimizer
     }
 
     fn fix_partitions(&mut self) {
         let mut changes_were_made = true;
+        let mut round = 0;
         while changes_were_made {
             changes_were_made = false;
+            round += 1;
 
-            for &c in &self.alphabet {
+            for c in &self.alphabet {
                 let mut new_partitions = Vec::new();
 
                 for partition in &self.partitions {
-                    let mut current_partitions_splits = HashMap::new();
+                    let mut current_partitions_splits = BTreeMap::new();
 
                     // can't split a partition of one. There shouldn't be partitions of zero too
                     if partition.len() == 1 {
@@ -80,6 +158,15 @@ impl DFAOptimizer {
                             .insert(state);
                     }
 
+                    if self.log_steps && current_partitions_splits.len() > 1 {
+                        self.log.steps.push(RefinementStep {
+                            round,
+                            symbol: c.clone(),
+                            group: sorted(partition),
+                            split_into: current_partitions_splits.values().map(sorted).collect(),
+                        });
+                    }
+
                     // add all the discovered new states
                     new_partitions.extend(current_partitions_splits.into_values());
                 }
@@ -92,7 +179,7 @@ impl DFAOptimizer {
         }
     }
 
-    fn remove_dead_partitions(&mut self) {
+    fn remove_dead_partitions(&mut self) -> Option<HashSet<State>> {
         // because it's already reduced we can only have one dead partition
         let dead_partition = self.partitions.iter()
             // we only really need a representative state
@@ -100,15 +187,13 @@ impl DFAOptimizer {
             .enumerate()
             .position(|(i, &state)| self.is_dead_partition(i, state));
 
-        if let Some(dead_position) = dead_partition {
-            self.partitions.remove(dead_position);
-        }
+        dead_partition.map(|dead_position| self.partitions.remove(dead_position))
     }
 
     fn is_dead_partition(&self, index: usize, state: State) -> bool {
         // we can't really remove a dead accepting state
         !self.old_acceptance_states.contains(&state) && self.alphabet.iter()
-            .all(|&c|
+            .all(|c|
                 if let Some(x) = self.partition_containing_transition(state, c) {
                     x == index // the transitions goes to itself
                 } else {
@@ -117,7 +202,7 @@ impl DFAOptimizer {
             )
     }
 
-    fn new_transitions(&mut self) -> (HashMap<(State, char), State>, HashSet<usize>) {
+    fn new_transitions(&mut self) -> (HashMap<(State, S), State>, HashSet<usize>) {
         // make the partition containing the initial state the initial partition
         let initial_partition = self.partitions
             .iter()
@@ -126,6 +211,11 @@ impl DFAOptimizer {
 
         self.partitions.swap(0, initial_partition);
 
+        let mut partition_of = HashMap::new();
+        for (index, partition) in self.partitions.iter().enumerate() {
+            partition.iter().for_each(|&state| { partition_of.insert(state, index); });
+        }
+
         let mut transitions = HashMap::new();
         let mut acceptance_states = HashSet::new();
 
@@ -137,9 +227,11 @@ impl DFAOptimizer {
             }
             let representative_state = partition.iter().next().expect("no empty partitions!");
 
-            for &c in &self.alphabet {
-                if let Some(to) = self.partition_containing_transition(*representative_state, c) {
-                    transitions.insert((from, c), to);
+            for c in &self.alphabet {
+                let to = self.old_movement(*representative_state, c)
+                    .and_then(|to| partition_of.get(&to));
+                if let Some(&to) = to {
+                    transitions.insert((from, c.clone()), to);
                 }
             }
         }
@@ -147,9 +239,13 @@ impl DFAOptimizer {
         (transitions, acceptance_states)
     }
 
-    fn partition_containing_transition(&self, state: State, c: char) -> Option<usize> {
-        let new_state = self.old_transitions.get(&(state, c))?;
-        let new_state = self.partition_containing(*new_state)?;
+    pub(super) fn old_movement(&self, state: State, c: &S) -> Option<State> {
+        self.old_transitions.get(state)?.get(c).copied()
+    }
+
+    fn partition_containing_transition(&self, state: State, c: &S) -> Option<usize> {
+        let new_state = self.old_movement(state, c)?;
+        let new_state = self.partition_containing(new_state)?;
         Some(new_state)
     }
 
@@ -163,10 +259,12 @@ impl DFAOptimizer {
 mod tests {
     use super::*;
     use super::super::DFAVisualizer;
+    use crate::automata::nfa::NFAutomata;
 
     #[test]
     fn test() {
-        DFAVisualizer::new(&DFAOptimizer::optimize(
+        // opening the page fails where there is nothing to open it with, but it is written first
+        let _ = DFAVisualizer::new(&DFAOptimizer::optimize(
             HashMap::from([
                 ((0, '0'), 1),
                 ((0, '1'), 5),
@@ -189,6 +287,82 @@ mod tests {
             7,
             vec!['0', '1'],
         )).show("test.html");
+        assert!(std::fs::read_to_string("test.html").unwrap().contains("graph LR"));
+    }
+
+    #[test]
+    fn minimization_log() {
+        let automata = DFAOptimizer::optimize_with(
+            by_state(HashMap::from([
+                ((0, 'a'), 1),
+                ((0, 'b'), 3),
+                ((1, 'a'), 3),
+                ((1, 'b'), 2),
+                ((2, 'a'), 3),
+                ((2, 'b'), 3),
+                ((3, 'a'), 3),
+                ((3, 'b'), 3),
+            ]), 3),
+            HashSet::from([2]),
+            3,
+            vec!['a', 'b'],
+            MinimizationAlgorithm::Moore,
+            true,
+        );
+        let log = automata.minimization().unwrap();
+
+        assert_eq!(vec![vec![0, 1, 3], vec![2]], log.initial_partitions);
+        assert_eq!((1, 'b', vec![0, 1, 3]), (log.steps[0].round, log.steps[0].symbol, log.steps[0].group.clone()));
+        assert_eq!((2, 'a', vec![0, 3]), (log.steps[1].round, log.steps[1].symbol, log.steps[1].group.clone()));
+        assert_eq!(Some(vec![3]), log.dead_states);
+        assert_eq!(3, log.merged_states.len());
+        assert_eq!(vec![0], log.merged_states[0]);
+    }
+
+    #[test]
+    fn hopcroft_matches_moore() {
+        let unoptimized = NFAutomata::try_from("(a|b)*abb(a|bc?)*").unwrap().into_determinate(false);
+        let optimize = |algorithm| DFAOptimizer::optimize_with(
+            unoptimized.transitions.clone(),
+            unoptimized.acceptance_states.clone(),
+            unoptimized.last_state,
+            vec!['a', 'b', 'c'],
+            algorithm,
+            false,
+        );
+        let moore = optimize(MinimizationAlgorithm::Moore);
+        let hopcroft = optimize(MinimizationAlgorithm::Hopcroft);
+
+        assert!(hopcroft.minimization().unwrap().steps.is_empty());
+        assert!(moore.is_isomorphic(&hopcroft));
+        // once numbered the same way, they must be the very same automata
+        let (moore, hopcroft) = (moore.canonical(), hopcroft.canonical());
+        assert_eq!(moore.last_state, hopcroft.last_state);
+        assert_eq!(moore.acceptance_states, hopcroft.acceptance_states);
+        assert_eq!(moore.transitions, hopcroft.transitions);
+    }
+
+    // cargo test --release -- --ignored --nocapture minimization_speed
+    #[test]
+    #[ignore]
+    fn minimization_speed() {
+        // a chain needs as many refinements as states, the worst case for Moore
+        let chain = |states: usize| (0..states)
+            .map(|state| HashMap::from([('a', (state + 1).min(states - 1)), ('b', 0)]))
+            .collect();
+
+        for states in [250, 500, 1000, 2000, 4000] {
+            let mut times = Vec::new();
+            for algorithm in [MinimizationAlgorithm::Moore, MinimizationAlgorithm::Hopcroft] {
+                let start = std::time::Instant::now();
+                let automata = DFAOptimizer::optimize_with(
+                    chain(states), HashSet::from([states - 1]), states - 1, vec!['a', 'b'], algorithm, false
+                );
+                times.push(start.elapsed());
+                assert_eq!(states - 1, automata.last_state);
+            }
+            println!("{states} states: moore {:?}, hopcroft {:?}", times[0], times[1]);
+        }
     }
 
     #[test]
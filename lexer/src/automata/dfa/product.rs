@@ -0,0 +1,136 @@
+use std::collections::{HashMap, HashSet};
+use super::automata::DFAutomata;
+use super::optimize::DFAOptimizer;
+use super::super::State;
+use crate::Letter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BooleanOperation {
+    Union,
+    Intersection,
+    /// strings accepted by the first automata but not by the second one
+    Difference,
+    SymmetricDifference,
+}
+
+impl BooleanOperation {
+    fn accepts(self, left: bool, right: bool) -> bool {
+        match self {
+            BooleanOperation::Union => left || right,
+            BooleanOperation::Intersection => left && right,
+            BooleanOperation::Difference => left && !right,
+            BooleanOperation::SymmetricDifference => left != right,
+        }
+    }
+
+    // whether a pair can still reach an accepting pair. An automata without a state can't accept anymore
+    fn may_accept(self, left_alive: bool, right_alive: bool) -> bool {
+        [(false, false), (false, true), (true, false), (true, true)]
+            .into_iter()
+            .any(|(left, right)| (left_alive || !left) && (right_alive || !right) && self.accepts(left, right))
+    }
+}
+
+// the state of each automata. A missing transition leaves the automata without a state
+type PairState = (Option<State>, Option<State>);
+
+impl<S: Letter> DFAutomata<S> {
+    /// runs both automata at the same time over the union of their alphabets, only keeping the
+    /// reachable pairs of states.
+    pub fn product(&self, other: &DFAutomata<S>, operation: BooleanOperation, optimized: bool) -> DFAutomata<S> {
+        let mut alphabet: Vec<S> = self.alphabet.iter().chain(&other.alphabet).cloned().collect();
+        alphabet.sort();
+        alphabet.dedup();
+
+        let mut acceptance_states = HashSet::new();
+        let mut transitions = HashMap::new();
+        let mut known_states: HashMap<PairState, State> = HashMap::from([((Some(0), Some(0)), 0)]);
+        let mut pending: Vec<PairState> = vec![(Some(0), Some(0))];
+        let mut current_state_id = 0;
+
+        while current_state_id < pending.len() {
+            let (left, right) = pending[current_state_id];
+
+            let left_accepts = left.is_some_and(|state| self.acceptance_states.contains(&state));
+            let right_accepts = right.is_some_and(|state| other.acceptance_states.contains(&state));
+            if operation.accepts(left_accepts, right_accepts) {
+                acceptance_states.insert(current_state_id);
+            }
+
+            for c in &alphabet {
+                let next = (
+                    left.and_then(|state| self.movement(state, c)),
+                    right.and_then(|state| other.movement(state, c)),
+                );
+                if !operation.may_accept(next.0.is_some(), next.1.is_some()) {
+                    continue;
+                }
+
+                let to = *known_states.entry(next).or_insert_with(|| {
+                    pending.push(next);
+                    pending.len() - 1
+                });
+                transitions.insert((current_state_id, c.clone()), to);
+            }
+
+            current_state_id += 1;
+        }
+
+        if optimized {
+            DFAOptimizer::optimize(transitions, acceptance_states, current_state_id - 1, alphabet)
+        } else {
+            DFAutomata::new(transitions, acceptance_states, current_state_id - 1)
+                .with_alphabet(alphabet)
+        }
+    }
+
+    pub fn union(&self, other: &DFAutomata<S>, optimized: bool) -> DFAutomata<S> {
+        self.product(other, BooleanOperation::Union, optimized)
+    }
+
+    pub fn intersection(&self, other: &DFAutomata<S>, optimized: bool) -> DFAutomata<S> {
+        self.product(other, BooleanOperation::Intersection, optimized)
+    }
+
+    pub fn difference(&self, other: &DFAutomata<S>, optimized: bool) -> DFAutomata<S> {
+        self.product(other, BooleanOperation::Difference, optimized)
+    }
+
+    pub fn symmetric_difference(&self, other: &DFAutomata<S>, optimized: bool) -> DFAutomata<S> {
+        self.product(other, BooleanOperation::SymmetricDifference, optimized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automata::Automata;
+
+    #[test]
+    fn operations() {
+        let even_a = DFAutomata::try_from("(b*ab*ab*)*").unwrap();
+        let ends_in_b = DFAutomata::try_from("(a|b)*b").unwrap();
+
+        let union = even_a.union(&ends_in_b, false);
+        assert!(union.test("aa") && union.test("ab") && !union.test("a"));
+
+        let intersection = even_a.intersection(&ends_in_b, true);
+        assert!(intersection.test("aab") && !intersection.test("aa") && !intersection.test("ab"));
+
+        let difference = even_a.difference(&ends_in_b, true);
+        assert!(difference.test("aa") && difference.test("") && !difference.test("aab"));
+
+        let symmetric_difference = even_a.symmetric_difference(&ends_in_b, false);
+        assert!(symmetric_difference.test("ab") && symmetric_difference.test("aa") && !symmetric_difference.test("aab"));
+    }
+
+    #[test]
+    fn different_alphabets() {
+        let intersection = DFAutomata::try_from("a*").unwrap()
+            .intersection(&DFAutomata::try_from("(a|b)(a|b)").unwrap(), true);
+
+        assert!(intersection.test("aa"));
+        assert!(!intersection.test("ab"));
+        assert_eq!(2, intersection.last_state);
+    }
+}
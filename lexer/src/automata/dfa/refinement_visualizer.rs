@@ -0,0 +1,77 @@
+use std::io;
+use std::collections::HashMap;
+use std::fmt::Display;
+use crate::mermaid_graph::{format_set, MermaidGraph};
+use super::optimize::MinimizationLog;
+use super::super::State;
+
+pub struct RefinementVisualizer {
+    last_id: usize,
+    // the node currently representing each group of states
+    groups: HashMap<Vec<State>, usize>,
+    mermaid: String,
+}
+
+impl RefinementVisualizer {
+    pub fn new<S: Display>(log: &MinimizationLog<S>) -> RefinementVisualizer {
+        let mut visualizer = RefinementVisualizer { last_id: 0, groups: HashMap::new(), mermaid: String::new() };
+
+        let mut all_states: Vec<State> = log.initial_partitions.concat();
+        all_states.sort();
+        visualizer.mermaid += &format!("\n        0[\"{}\"]", format_set(&all_states));
+        visualizer.add_connection(0, "accepting or not", &log.initial_partitions);
+        for step in &log.steps {
+            let from = visualizer.group_id(&step.group);
+            let description = format!("round {}, on '{}'", step.round, step.symbol);
+            visualizer.add_connection(from, &description, &step.split_into);
+        }
+
+        if let Some(dead_states) = &log.dead_states {
+            let id = visualizer.group_id(dead_states);
+            visualizer.mermaid += &format!("\n        style {id} fill:#f66");
+        }
+
+        for (new_state, merged_states) in log.merged_states.iter().enumerate() {
+            let id = visualizer.group_id(merged_states);
+            visualizer.mermaid += &format!("\n        {id}[\"{new_state} = {}\"]", format_set(merged_states));
+            visualizer.mermaid += &format!("\n        style {id} fill:#9f9");
+        }
+
+        visualizer
+    }
+
+    fn group_id(&mut self, group: &[State]) -> usize {
+        if let Some(&id) = self.groups.get(group) {
+            return id;
+        }
+
+        // the group wasn't split from any other one, so it has no node yet
+        self.last_id += 1;
+        self.groups.insert(group.to_vec(), self.last_id);
+        self.mermaid += &format!("\n        {}[\"{}\"]", self.last_id, format_set(group));
+        self.last_id
+    }
+
+    fn add_connection(&mut self, from: usize, description: &str, groups: &[Vec<State>]) {
+        for group in groups {
+            self.last_id += 1;
+            self.groups.insert(group.clone(), self.last_id);
+            self.mermaid += &format!("\n        {}[\"{}\"]", self.last_id, format_set(group));
+            self.mermaid += &format!("\n        {from} -->|\"{description}\"| {}", self.last_id);
+        }
+    }
+
+    pub fn show(&self, path: &str) -> io::Result<String> {
+        self.generate_and_open_graph(path)
+    }
+}
+
+impl MermaidGraph for RefinementVisualizer {
+    fn header(&self) -> &'static str {
+        "flowchart TD"
+    }
+
+    fn get_mermaid_content(&self) -> &str {
+        &self.mermaid
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::Entry;
+use super::automata::DFAutomata;
+use super::super::State;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchReport {
+    pub accepted: bool,
+    /// amount of chars that were consumed before the automata got stuck or the input ended
+    pub consumed: usize,
+    /// chars that had a transition on the state where the automata stopped
+    pub expected: Vec<char>,
+    /// shortest string that leads from the state where the automata stopped to an accepting state
+    pub completion: Option<String>,
+}
+
+impl DFAutomata {
+    pub fn match_report(&self, input: &str) -> MatchReport {
+        let mut state = 0;
+        let mut consumed = 0;
+
+        for c in input.chars() {
+            match self.movement(state, &c) {
+                Some(next) => state = next,
+                None => break,
+            }
+            consumed += 1;
+        }
+
+        MatchReport {
+            accepted: consumed == input.chars().count() && self.acceptance_states.contains(&state),
+            consumed,
+            expected: self.expected_chars(state),
+            completion: self.shortest_completion(state),
+        }
+    }
+
+    fn expected_chars(&self, state: State) -> Vec<char> {
+        let mut chars: Vec<char> = self.transitions.get(state)
+            .into_iter()
+            .flat_map(|edges| edges.keys().copied())
+            .collect();
+        chars.sort();
+        chars
+    }
+
+    fn shortest_completion(&self, state: State) -> Option<String> {
+        // breadth first search so the first accepting state we find is the closest one
+        let mut previous: HashMap<State, Option<(State, char)>> = HashMap::from([(state, None)]);
+        let mut pending = VecDeque::from([state]);
+
+        while let Some(current) = pending.pop_front() {
+            if self.acceptance_states.contains(&current) {
+                let mut completion = Vec::new();
+                let mut step = current;
+                while let Some((from, c)) = previous[&step] {
+                    completion.push(c);
+                    step = from;
+                }
+                return Some(completion.into_iter().rev().collect());
+            }
+
+            for c in self.expected_chars(current) {
+                let next = self.movement(current, &c).expect("expected chars must have a transition");
+                if let Entry::Vacant(entry) = previous.entry(next) {
+                    entry.insert(Some((current, c)));
+                    pending.push_back(next);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stuck_on_unexpected_char() {
+        let automata = DFAutomata::try_from("ab(c|d)e").unwrap();
+        let report = automata.match_report("abx");
+
+        assert!(!report.accepted);
+        assert_eq!(2, report.consumed);
+        assert_eq!(vec!['c', 'd'], report.expected);
+        assert_eq!(Some("ce".to_string()), report.completion);
+    }
+
+    #[test]
+    fn input_ended_early() {
+        let automata = DFAutomata::try_from("abc").unwrap();
+        let report = automata.match_report("a");
+
+        assert!(!report.accepted);
+        assert_eq!(1, report.consumed);
+        assert_eq!(Some("bc".to_string()), report.completion);
+        assert!(automata.match_report("abc").accepted);
+    }
+}
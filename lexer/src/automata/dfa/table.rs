@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use super::automata::DFAutomata;
+use super::super::{Automata, Inspect};
+
+/// the entry of the tables for a missing transition
+pub const DEAD: u32 = u32::MAX;
+
+/// Groups the chars every state treats the same way. The class 0 holds every char outside the
+/// alphabet, which has no transitions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharClasses {
+    ascii: [u32; 128],
+    other: HashMap<char, u32>,
+    count: usize,
+}
+
+impl CharClasses {
+    fn new(automata: &DFAutomata) -> CharClasses {
+        let mut classes = CharClasses { ascii: [0; 128], other: HashMap::new(), count: 1 };
+        // chars with the same transitions on every state share the class
+        let mut known_columns: HashMap<Vec<Option<usize>>, u32> = HashMap::new();
+        for c in automata.alphabet() {
+            let column: Vec<Option<usize>> = (0..=automata.last_state)
+                .map(|state| automata.movement(state, &c))
+                .collect();
+
+            let class = *known_columns.entry(column).or_insert_with(|| {
+                classes.count += 1;
+                classes.count as u32 - 1
+            });
+
+            if c.is_ascii() {
+                classes.ascii[c as usize] = class;
+            } else {
+                classes.other.insert(c, class);
+            }
+        }
+
+        classes
+    }
+
+    #[inline]
+    pub fn class_of(&self, c: char) -> u32 {
+        if c.is_ascii() {
+            self.ascii[c as usize]
+        } else {
+            self.other.get(&c).copied().unwrap_or(0)
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// the class of every char in the alphabet, sorted by char
+    pub fn chars(&self) -> Vec<(char, u32)> {
+        let mut chars: Vec<(char, u32)> = (0..128u8)
+            .map(|c| (c as char, self.ascii[c as usize]))
+            .filter(|(_, class)| *class != 0)
+            .chain(self.other.iter().map(|(&c, &class)| (c, class)))
+            .collect();
+        chars.sort();
+        chars
+    }
+}
+
+/// A DFA compiled into a transition table with a row for each state and a column for each class
+/// of chars, as used by scanner generators.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DenseDFA {
+    classes: CharClasses,
+    table: Vec<u32>,
+    accepting: Vec<bool>,
+}
+
+impl DenseDFA {
+    pub fn classes(&self) -> &CharClasses {
+        &self.classes
+    }
+
+    /// the next state of `state` on `class` is at `state * classes + class`
+    pub fn table(&self) -> &[u32] {
+        &self.table
+    }
+
+    pub fn accepting(&self) -> &[bool] {
+        &self.accepting
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.accepting.len()
+    }
+
+    #[inline]
+    pub fn next_state(&self, state: u32, c: char) -> u32 {
+        self.table[state as usize * self.classes.count + self.classes.class_of(c) as usize]
+    }
+
+    /// compresses the table by overlapping its rows, like flex does
+    pub fn compressed(&self) -> CombDFA {
+        let classes = self.classes.count;
+        let mut base = Vec::with_capacity(self.state_count());
+        let mut next: Vec<u32> = Vec::new();
+        let mut check: Vec<u32> = Vec::new();
+
+        for state in 0..self.state_count() {
+            let row = &self.table[state * classes..(state + 1) * classes];
+            let used: Vec<usize> = (0..classes).filter(|&class| row[class] != DEAD).collect();
+
+            // the first displacement where the row fits in the free slots
+            let displacement = (0..)
+                .find(|&displacement| used.iter().all(|&class| check.get(displacement + class).is_none_or(|&owner| owner == DEAD)))
+                .expect("there is always room after the last slot");
+
+            if next.len() < displacement + classes {
+                next.resize(displacement + classes, DEAD);
+                check.resize(displacement + classes, DEAD);
+            }
+            for class in used {
+                next[displacement + class] = row[class];
+                check[displacement + class] = state as u32;
+            }
+            base.push(displacement as u32);
+        }
+
+        CombDFA { classes: self.classes.clone(), base, next, check, accepting: self.accepting.clone() }
+    }
+}
+
+impl From<&DFAutomata> for DenseDFA {
+    fn from(value: &DFAutomata) -> Self {
+        let classes = CharClasses::new(value);
+        let mut table = vec![DEAD; (value.last_state + 1) * classes.count];
+
+        for (from, &c, to) in value.edges() {
+            table[from * classes.count + classes.class_of(c) as usize] = to as u32;
+        }
+
+        DenseDFA {
+            accepting: (0..=value.last_state).map(|state| value.acceptance_states.contains(&state)).collect(),
+            classes,
+            table,
+        }
+    }
+}
+
+impl Automata for DenseDFA {
+    fn test(&self, input: &str) -> bool {
+        let mut state = 0;
+
+        for c in input.chars() {
+            state = self.next_state(state, c);
+            if state == DEAD {
+                return false;
+            }
+        }
+
+        self.accepting[state as usize]
+    }
+}
+
+/// A DFA whose rows are overlapped in a single vector. The transition of a state on a class is at
+/// `base[state] + class`, when `check` says that slot belongs to the state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CombDFA {
+    classes: CharClasses,
+    base: Vec<u32>,
+    next: Vec<u32>,
+    check: Vec<u32>,
+    accepting: Vec<bool>,
+}
+
+impl CombDFA {
+    pub fn classes(&self) -> &CharClasses {
+        &self.classes
+    }
+
+    pub fn base(&self) -> &[u32] {
+        &self.base
+    }
+
+    pub fn next(&self) -> &[u32] {
+        &self.next
+    }
+
+    pub fn check(&self) -> &[u32] {
+        &self.check
+    }
+
+    pub fn accepting(&self) -> &[bool] {
+        &self.accepting
+    }
+
+    #[inline]
+    pub fn next_state(&self, state: u32, c: char) -> u32 {
+        let index = self.base[state as usize] as usize + self.classes.class_of(c) as usize;
+        if self.check[index] == state {
+            self.next[index]
+        } else {
+            DEAD
+        }
+    }
+}
+
+impl From<&DFAutomata> for CombDFA {
+    fn from(value: &DFAutomata) -> Self {
+        DenseDFA::from(value).compressed()
+    }
+}
+
+impl Automata for CombDFA {
+    fn test(&self, input: &str) -> bool {
+        let mut state = 0;
+
+        for c in input.chars() {
+            state = self.next_state(state, c);
+            if state == DEAD {
+                return false;
+            }
+        }
+
+        self.accepting[state as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables() {
+        let automata = DFAutomata::try_from("(a|b|c)*abb|d(e|f)*").unwrap();
+        let dense = DenseDFA::from(&automata);
+        let comb = dense.compressed();
+
+        // a, b, c and d behave differently while e behaves like f, plus the class of the other chars
+        assert_eq!(6, dense.classes().count());
+        assert_eq!(dense.classes().class_of('e'), dense.classes().class_of('f'));
+        assert_eq!(0, dense.classes().class_of('z'));
+        assert_eq!(dense.state_count() * 6, dense.table().len());
+        assert!(comb.next().len() < dense.table().len());
+
+        for input in ["abb", "cabb", "ab", "def", "d", "de", "abbd", "zabb", ""] {
+            assert_eq!(automata.test(input), dense.test(input));
+            assert_eq!(automata.test(input), comb.test(input));
+        }
+    }
+
+    // cargo test --release -- --ignored --nocapture table_speed
+    #[test]
+    #[ignore]
+    fn table_speed() {
+        let automata = DFAutomata::try_from("(a|b)*a(a|b)(a|b)(a|b)(a|b)(a|b)(a|b)(a|b)(a|b)").unwrap();
+        let dense = DenseDFA::from(&automata);
+        let comb = dense.compressed();
+        let input = "ab".repeat(500_000) + "aaaaaaaaa";
+
+        let candidates: [(&str, &dyn Automata); 3] = [("hash map", &automata), ("dense", &dense), ("comb", &comb)];
+        for (name, candidate) in candidates {
+            let start = std::time::Instant::now();
+            assert!(candidate.test(&input));
+            println!("{name}: {:?}", start.elapsed());
+        }
+        println!("{} states, dense table {} entries, comb vector {} entries", dense.state_count(), dense.table().len(), comb.next().len());
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use super::automata::DFAutomata;
+use super::table::DEAD;
+use super::super::Automata;
+
+/// A DFA reading the UTF-8 encoding of the strings one byte at a time, so it can run over raw byte
+/// buffers. Each transition on a char becomes a path over its bytes, the paths leaving a state share
+/// their common prefixes. Invalid UTF-8 never matches: the byte sequences that don't encode a char
+/// of the alphabet have no transitions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteDFA {
+    table: Vec<u32>,
+    accepting: Vec<bool>,
+}
+
+impl ByteDFA {
+    /// the next state of `state` on `byte` is at `state * 256 + byte`
+    pub fn table(&self) -> &[u32] {
+        &self.table
+    }
+
+    pub fn accepting(&self) -> &[bool] {
+        &self.accepting
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.accepting.len()
+    }
+
+    #[inline]
+    pub fn next_state(&self, state: u32, byte: u8) -> u32 {
+        self.table[state as usize * 256 + byte as usize]
+    }
+
+    pub fn test_bytes(&self, input: &[u8]) -> bool {
+        let mut state = 0;
+
+        for &byte in input {
+            state = self.next_state(state, byte);
+            if state == DEAD {
+                return false;
+            }
+        }
+
+        self.accepting[state as usize]
+    }
+
+    fn add_state(&mut self, accepting: bool) -> u32 {
+        self.table.extend([DEAD; 256]);
+        self.accepting.push(accepting);
+        self.accepting.len() as u32 - 1
+    }
+}
+
+impl From<&DFAutomata> for ByteDFA {
+    fn from(value: &DFAutomata) -> Self {
+        let mut automata = ByteDFA { table: Vec::new(), accepting: Vec::new() };
+        // the states of the DFA keep their ids, the ones inside the byte paths come after them
+        for state in 0..=value.last_state {
+            automata.add_state(value.acceptance_states.contains(&state));
+        }
+
+        let mut transitions: Vec<(usize, &char, usize)> = value.edges().collect();
+        transitions.sort();
+
+        // the state reached from a state after reading the first bytes of a char
+        let mut paths: HashMap<(usize, Vec<u8>), u32> = HashMap::new();
+        for (from, c, to) in transitions {
+            let mut buffer = [0; 4];
+            let bytes = c.encode_utf8(&mut buffer).as_bytes();
+
+            let mut state = from as u32;
+            for i in 0..bytes.len() - 1 {
+                state = *paths.entry((from, bytes[..=i].to_vec())).or_insert_with(|| {
+                    let next = automata.add_state(false);
+                    automata.table[state as usize * 256 + bytes[i] as usize] = next;
+                    next
+                });
+            }
+            automata.table[state as usize * 256 + bytes[bytes.len() - 1] as usize] = to as u32;
+        }
+
+        automata
+    }
+}
+
+impl Automata for ByteDFA {
+    fn test(&self, input: &str) -> bool {
+        self.test_bytes(input.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_automata() {
+        let automata = DFAutomata::try_from("(é|€)*añ|😀").unwrap();
+        let bytes = ByteDFA::from(&automata);
+
+        for input in ["añ", "éé€añ", "😀", "ñ", "éa", ""] {
+            assert_eq!(automata.test(input), bytes.test(input));
+        }
+
+        // é and ñ only share their first byte
+        assert_eq!("é".as_bytes()[0], "ñ".as_bytes()[0]);
+        assert!(bytes.test_bytes(b"\xc3\xa9a\xc3\xb1"));
+        // a truncated é, a lone continuation byte and an invalid byte
+        assert!(!bytes.test_bytes(b"\xc3a\xc3\xb1"));
+        assert!(!bytes.test_bytes(b"\xa9a\xc3\xb1"));
+        assert!(!bytes.test_bytes(b"\xffa\xc3\xb1"));
+    }
+}
@@ -1,33 +1,98 @@
+use std::io;
 use std::collections::{HashMap, HashSet};
-use crate::mermaid_graph::MermaidGraph;
+use std::fmt::Display;
+use crate::mermaid_graph::{format_set, MermaidGraph};
 use super::automata::{DFAutomata};
-use super::super::State;
+use crate::{Letter, Symbol};
+use super::super::{Inspect, State, TraceStep};
 
 pub struct DFAVisualizer {
     mermaid: String,
+    // the transitions in the order they were drawn, mermaid identifies links by that order
+    edges: Vec<(State, State)>,
 }
 
 impl DFAVisualizer {
-   pub fn new(automata: &DFAutomata) -> DFAVisualizer {
-       let mut visualizer = DFAVisualizer { mermaid: String::new() };
-       visualizer.add_descriptions(automata.last_state, &automata.acceptance_states);
-       visualizer.add_transitions(&automata.transitions);
-
-       visualizer
+   pub fn new<S: Letter + Display>(automata: &DFAutomata<S>) -> DFAVisualizer {
+       DFAVisualizer::build(automata, false)
    }
 
-    fn add_descriptions(&mut self, last_id: State, accepted_states: &HashSet<State>) {
-        (0..=last_id).for_each(|id| {
-            if accepted_states.contains(&id) {
-                self.mermaid += &format!("\n        {id}((({id})))");
+    /// draws the automata without its sink states, nor the transitions going into them
+    pub fn without_sink<S: Letter + Display>(automata: &DFAutomata<S>) -> DFAVisualizer {
+        DFAVisualizer::build(automata, true)
+    }
+
+    fn build<S: Letter + Display>(automata: &DFAutomata<S>, hide_sink: bool) -> DFAVisualizer {
+        let hidden: HashSet<State> = (0..automata.state_count())
+            .filter(|&state| hide_sink && automata.is_sink(state))
+            .collect();
+
+        let mut visualizer = DFAVisualizer { mermaid: String::new(), edges: Vec::new() };
+        visualizer.add_descriptions(automata, &hidden);
+        visualizer.add_transitions(automata, &hidden);
+
+        visualizer
+    }
+
+    fn add_descriptions<S: Letter>(&mut self, automata: &DFAutomata<S>, hidden: &HashSet<State>) {
+        (0..automata.state_count()).filter(|id| !hidden.contains(id)).for_each(|id| {
+            let label = match automata.subsets() {
+                Some(subsets) => format!("\"{} = {}\"", Self::state_name(id), format_set(&subsets[id])),
+                None => id.to_string(),
+            };
+
+            if automata.is_accepting(id) {
+                self.mermaid += &format!("\n        {id}((({label})))");
             } else {
-                self.mermaid += &format!("\n        {id}(({id}))")
+                self.mermaid += &format!("\n        {id}(({label}))")
             }
         });
     }
 
-    fn add_transition(&mut self, from: State, to: State, chars: HashSet<char>) {
-        let mut chars: Vec<char> = chars.into_iter()
+    // names states A, B, ..., Z, AA, AB, ... like the textbook does
+    fn state_name(id: State) -> String {
+        let mut name = Vec::new();
+        let mut id = id + 1;
+        while id > 0 {
+            id -= 1;
+            name.push((b'A' + (id % 26) as u8) as char);
+            id /= 26;
+        }
+        name.into_iter().rev().collect()
+    }
+
+    /// the Dstates/Dtran table of the subset construction, as a markdown table. Only available when
+    /// the automata remembers which NFA states each of its states represents.
+    pub fn dstates_table<S: Letter + Display>(automata: &DFAutomata<S>) -> Option<String> {
+        let subsets = automata.subsets()?;
+
+        // only the symbols with transitions get a column
+        let alphabet: Vec<S> = automata.alphabet()
+            .into_iter()
+            .filter(|c| (0..automata.state_count()).any(|state| automata.movement(state, c).is_some()))
+            .collect();
+
+        let mut table = String::from("| | T |");
+        alphabet.iter().for_each(|c| table += &format!(" ε-closure(move(T, {c})) |"));
+        table += &format!("\n|---|---|{}", "---|".repeat(alphabet.len()));
+
+        for (id, subset) in subsets.iter().enumerate() {
+            let marker = if automata.is_accepting(id) { "*" } else { "" };
+            table += &format!("\n| {marker}{} | {} |", Self::state_name(id), format_set(subset));
+
+            for c in &alphabet {
+                match automata.movement(id, c) {
+                    Some(to) => table += &format!(" {} = {} |", format_set(&subsets[to]), Self::state_name(to)),
+                    None => table += " ∅ |",
+                }
+            }
+        }
+
+        Some(table)
+    }
+
+    fn add_transition<S: Letter + Display>(&mut self, from: State, to: State, chars: HashSet<S>) {
+        let mut chars: Vec<S> = chars.into_iter()
             .collect();
         chars.sort();
 
@@ -39,27 +104,38 @@ impl DFAVisualizer {
             .collect();
 
         self.mermaid += &format!("\n        {from} -->|\"{chars}\"| {to}");
+        self.edges.push((from, to));
     }
 
-    fn add_transitions(&mut self, transitions: &HashMap<(State, char), State>) {
-        let mut new_transitions: HashMap<(State, State), HashSet<char>> = HashMap::new();
+    fn add_transitions<S: Letter + Display>(&mut self, automata: &DFAutomata<S>, hidden: &HashSet<State>) {
+        let mut new_transitions: HashMap<(State, State), HashSet<S>> = HashMap::new();
 
-        for ((from, c), to) in transitions.iter() {
-            if let Some(chars) = new_transitions.get_mut(&(*from, *to)) {
-                chars.insert(*c);
+        for (from, symbol, to) in automata.transitions().into_iter().filter(|(from, _, to)| !hidden.contains(from) && !hidden.contains(to)) {
+            let Symbol::Character(c) = symbol else { continue };
+            if let Some(chars) = new_transitions.get_mut(&(from, to)) {
+                chars.insert(c);
             } else {
-                new_transitions.insert((*from, *to), HashSet::from([*c]));
+                new_transitions.insert((from, to), HashSet::from([c]));
             }
         }
 
+        // sorted so the same automata is always drawn the same way
+        let mut new_transitions: Vec<((State, State), HashSet<S>)> = new_transitions.into_iter().collect();
+        new_transitions.sort_by_key(|((from, to), _)| (*from, *to));
         new_transitions.into_iter().for_each(|((from, to), chars)| {
             self.add_transition(from, to, chars)
         })
     }
 
-    pub fn show(&self, path: &str) -> String {
+    pub fn show(&self, path: &str) -> io::Result<String> {
         self.generate_and_open_graph(path)
     }
+
+    pub fn show_trace(&self, trace: &[TraceStep], path: &str) -> io::Result<String> {
+        self.generate_and_open_trace(path, trace, |from, _, to|
+            self.edges.iter().position(|edge| *edge == (from, to))
+        )
+    }
 }
 
 impl MermaidGraph for DFAVisualizer {
@@ -71,3 +147,25 @@ impl MermaidGraph for DFAVisualizer {
         &self.mermaid
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automata::nfa::NFAutomata;
+
+    #[test]
+    fn dstates_table() {
+        let automata = NFAutomata::try_from("a|b").unwrap().into_determinate(false);
+        let table = DFAVisualizer::dstates_table(&automata).unwrap();
+
+        assert_eq!(vec![0, 1, 3], automata.subsets().unwrap()[0]);
+        assert_eq!(
+            "| | T | ε-closure(move(T, a)) | ε-closure(move(T, b)) |\n\
+             |---|---|---|---|\n\
+             | A | {0,1,3} | {2,5} = B | {4,5} = C |\n\
+             | *B | {2,5} | ∅ | ∅ |\n\
+             | *C | {4,5} | ∅ | ∅ |",
+            table
+        );
+    }
+}
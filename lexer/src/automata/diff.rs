@@ -0,0 +1,486 @@
+use std::io;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+use crate::mermaid_graph::MermaidGraph;
+use crate::{Letter, Symbol};
+use super::{Inspect, State};
+
+type Transition<S> = (State, Symbol<S>, State);
+
+// the parts of an automata the comparison looks at, no matter if it is deterministic
+struct Graph<S> {
+    // one more than the highest id, the size of anything indexed by the states
+    ids: usize,
+    states: Vec<State>,
+    transitions: Vec<Transition<S>>,
+    start_states: HashSet<State>,
+    acceptance_states: HashSet<State>,
+}
+
+impl<S: Letter> Graph<S> {
+    fn new(automata: &impl Inspect<S>) -> Graph<S> {
+        let states = automata.states();
+        Graph {
+            ids: states.last().map_or(0, |state| state + 1),
+            states,
+            transitions: automata.transitions(),
+            start_states: automata.start_states().into_iter().collect(),
+            acceptance_states: automata.acceptance_states().into_iter().collect(),
+        }
+    }
+}
+
+// in and out transitions, along with what distinguishes a state under any renaming
+struct Indexed<'a, S> {
+    graph: &'a Graph<S>,
+    transitions: HashSet<Transition<S>>,
+    outgoing: Vec<Vec<(Symbol<S>, State)>>,
+    incoming: Vec<Vec<(Symbol<S>, State)>>,
+}
+
+type Signature<S> = (bool, bool, Vec<Symbol<S>>, Vec<Symbol<S>>);
+// a state along with the transition through which the search found it, if any
+type Found<S> = (State, Option<(Symbol<S>, State)>);
+
+impl<'a, S: Letter> Indexed<'a, S> {
+    fn new(graph: &'a Graph<S>) -> Indexed<'a, S> {
+        let mut outgoing = vec![Vec::new(); graph.ids];
+        let mut incoming = vec![Vec::new(); graph.ids];
+        for (from, symbol, to) in &graph.transitions {
+            outgoing[*from].push((symbol.clone(), *to));
+            incoming[*to].push((symbol.clone(), *from));
+        }
+        outgoing.iter_mut().chain(&mut incoming).for_each(|edges| edges.sort());
+
+        Indexed { graph, transitions: graph.transitions.iter().cloned().collect(), outgoing, incoming }
+    }
+
+    fn signature(&self, state: State) -> Signature<S> {
+        (
+            self.graph.start_states.contains(&state),
+            self.graph.acceptance_states.contains(&state),
+            self.outgoing[state].iter().map(|(symbol, _)| symbol.clone()).collect(),
+            self.incoming[state].iter().map(|(symbol, _)| symbol.clone()).collect(),
+        )
+    }
+
+    // the states in the order a breadth first search from the start states finds them
+    fn search_order(&self) -> Vec<Found<S>> {
+        let mut start_states: Vec<State> = self.graph.start_states.iter().copied().collect();
+        start_states.sort();
+
+        let mut visited = vec![false; self.graph.ids];
+        let mut order = Vec::new();
+        let mut pending: VecDeque<Found<S>> = VecDeque::new();
+        for state in start_states.into_iter().chain(self.graph.states.iter().copied()) {
+            if visited[state] {
+                continue;
+            }
+            visited[state] = true;
+            pending.push_back((state, None));
+
+            while let Some((state, found_through)) = pending.pop_front() {
+                order.push((state, found_through));
+                for (symbol, to) in &self.outgoing[state] {
+                    if !visited[*to] {
+                        visited[*to] = true;
+                        pending.push_back((*to, Some((symbol.clone(), state))));
+                    }
+                }
+            }
+        }
+
+        order
+    }
+}
+
+/// How two automata differ, in terms of a pairing of their states. Transitions and states are
+/// given with the ids of the automata they belong to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutomataDiff<S = char> {
+    /// the pairs of states considered the same, sorted by the state of the first automata
+    pub mapping: Vec<(State, State)>,
+    pub unmatched_left: Vec<State>,
+    pub unmatched_right: Vec<State>,
+    /// transitions of the first automata missing in the second one
+    pub missing_transitions: Vec<Transition<S>>,
+    /// transitions of the second automata missing in the first one
+    pub extra_transitions: Vec<Transition<S>>,
+    /// paired states where only one of them is accepting
+    pub acceptance_mismatches: Vec<(State, State)>,
+    /// paired states where only one of them is a start state
+    pub start_mismatches: Vec<(State, State)>,
+    // the transitions both automata have, with the ids of the first one
+    common_transitions: Vec<Transition<S>>,
+}
+
+impl<S> Default for AutomataDiff<S> {
+    fn default() -> Self {
+        AutomataDiff {
+            mapping: Vec::new(),
+            unmatched_left: Vec::new(),
+            unmatched_right: Vec::new(),
+            missing_transitions: Vec::new(),
+            extra_transitions: Vec::new(),
+            acceptance_mismatches: Vec::new(),
+            start_mismatches: Vec::new(),
+            common_transitions: Vec::new(),
+        }
+    }
+}
+
+impl<S: Letter> AutomataDiff<S> {
+    /// whether the automata are the same up to renaming their states
+    pub fn is_isomorphic(&self) -> bool {
+        self.unmatched_left.is_empty() && self.unmatched_right.is_empty()
+            && self.missing_transitions.is_empty() && self.extra_transitions.is_empty()
+            && self.acceptance_mismatches.is_empty() && self.start_mismatches.is_empty()
+    }
+
+    /// finds a renaming of the states making both automata the same, otherwise reports what differs
+    /// between them
+    pub fn new(left: &impl Inspect<S>, right: &impl Inspect<S>) -> AutomataDiff<S> {
+        let (left, right) = (Graph::new(left), Graph::new(right));
+        let left = Indexed::new(&left);
+        let right = Indexed::new(&right);
+
+        let mapping = find_isomorphism(&left, &right)
+            .unwrap_or_else(|| pair_greedily(&left, &right));
+        AutomataDiff::from_mapping(&left, &right, mapping)
+    }
+
+    fn from_mapping(left: &Indexed<S>, right: &Indexed<S>, mapping: Vec<Option<State>>) -> AutomataDiff<S> {
+        let mut inverse = vec![None; right.graph.ids];
+        mapping.iter().enumerate().for_each(|(from, to)| if let Some(to) = to { inverse[*to] = Some(from) });
+
+        let mut diff = AutomataDiff {
+            mapping: mapping.iter().enumerate().filter_map(|(from, to)| Some((from, (*to)?))).collect(),
+            unmatched_left: left.graph.states.iter().copied().filter(|&state| mapping[state].is_none()).collect(),
+            unmatched_right: right.graph.states.iter().copied().filter(|&state| inverse[state].is_none()).collect(),
+            ..AutomataDiff::default()
+        };
+
+        for (from, symbol, to) in &left.graph.transitions {
+            match (mapping[*from], mapping[*to]) {
+                (Some(new_from), Some(new_to)) if right.transitions.contains(&(new_from, symbol.clone(), new_to)) =>
+                    diff.common_transitions.push((*from, symbol.clone(), *to)),
+                _ => diff.missing_transitions.push((*from, symbol.clone(), *to)),
+            }
+        }
+        for (from, symbol, to) in &right.graph.transitions {
+            match (inverse[*from], inverse[*to]) {
+                (Some(old_from), Some(old_to)) if left.transitions.contains(&(old_from, symbol.clone(), old_to)) => {},
+                _ => diff.extra_transitions.push((*from, symbol.clone(), *to)),
+            }
+        }
+
+        for &(left_state, right_state) in &diff.mapping {
+            if left.graph.acceptance_states.contains(&left_state) != right.graph.acceptance_states.contains(&right_state) {
+                diff.acceptance_mismatches.push((left_state, right_state));
+            }
+            if left.graph.start_states.contains(&left_state) != right.graph.start_states.contains(&right_state) {
+                diff.start_mismatches.push((left_state, right_state));
+            }
+        }
+
+        for transitions in [&mut diff.common_transitions, &mut diff.missing_transitions, &mut diff.extra_transitions] {
+            transitions.sort();
+        }
+        diff
+    }
+}
+
+// Backtracks over the pairings of states with the same signature, in search order so every new pair
+// is constrained by the ones before it. The states already paired are kept on a stack instead of
+// recursing, so automata of any size can be compared.
+struct Isomorphism<'a, S> {
+    left: &'a Indexed<'a, S>,
+    right: &'a Indexed<'a, S>,
+    order: Vec<Found<S>>,
+    // the signatures of the states of both automata, numbered the same way. The states whose
+    // signature isn't in the second automata can't be paired
+    left_signatures: Vec<Option<usize>>,
+    right_signatures: Vec<usize>,
+    // the states of the second automata with each signature
+    right_states: Vec<Vec<State>>,
+    mapping: Vec<Option<State>>,
+    inverse: Vec<Option<State>>,
+}
+
+impl<S: Letter> Isomorphism<'_, S> {
+    fn search(&mut self) -> bool {
+        // for each state of the order paired so far and the one being paired, the index of the next
+        // candidate to try
+        let mut next_candidates = vec![0];
+
+        while let Some(&next_candidate) = next_candidates.last() {
+            let index = next_candidates.len() - 1;
+            let Some(&(from, _)) = self.order.get(index) else {
+                return true;
+            };
+
+            // the pairing tried before, if any, didn't work out
+            if let Some(to) = self.mapping[from].take() {
+                self.inverse[to] = None;
+            }
+
+            let found = self.candidates(index)
+                .enumerate()
+                .skip(next_candidate)
+                .find(|&(_, to)| self.inverse[to].is_none() && self.consistent(from, to));
+
+            match found {
+                Some((i, to)) => {
+                    self.mapping[from] = Some(to);
+                    self.inverse[to] = Some(from);
+                    *next_candidates.last_mut().expect("the state is being paired") = i + 1;
+                    next_candidates.push(0);
+                },
+                None => {
+                    next_candidates.pop();
+                },
+            }
+        }
+
+        false
+    }
+
+    // the states with the same signature the state at this index of the order can be paired with.
+    // The state it was found from is already paired, so only the states its pair reaches through
+    // the same symbol are left
+    fn candidates(&self, index: usize) -> impl Iterator<Item=State> + '_ {
+        let (from, found_through) = &self.order[index];
+        let signature = self.left_signatures[*from];
+
+        let reached = found_through.as_ref().map(|(symbol, previous)| {
+            let previous = self.mapping[*previous].expect("the states are paired in search order");
+            self.right.outgoing[previous].iter()
+                .filter(move |(other, to)| other == symbol && Some(self.right_signatures[*to]) == signature)
+                .map(|(_, to)| *to)
+        });
+        let with_signature = signature.filter(|_| found_through.is_none())
+            .map(|signature| self.right_states[signature].iter().copied());
+
+        reached.into_iter().flatten().chain(with_signature.into_iter().flatten())
+    }
+
+    // whether pairing the states keeps the transitions between the paired states
+    fn consistent(&self, from: State, to: State) -> bool {
+        let (left, right) = (self.left, self.right);
+        let image = |state: State| if state == from { Some(to) } else { self.mapping[state] };
+        let preimage = |state: State| if state == to { Some(from) } else { self.inverse[state] };
+
+        left.outgoing[from].iter().all(|(symbol, next)| image(*next).is_none_or(|next| right.transitions.contains(&(to, symbol.clone(), next))))
+            && left.incoming[from].iter().all(|(symbol, previous)| image(*previous).is_none_or(|previous| right.transitions.contains(&(previous, symbol.clone(), to))))
+            && right.outgoing[to].iter().all(|(symbol, next)| preimage(*next).is_none_or(|next| left.transitions.contains(&(from, symbol.clone(), next))))
+            && right.incoming[to].iter().all(|(symbol, previous)| preimage(*previous).is_none_or(|previous| left.transitions.contains(&(previous, symbol.clone(), from))))
+    }
+}
+
+fn find_isomorphism<S: Letter>(left: &Indexed<S>, right: &Indexed<S>) -> Option<Vec<Option<State>>> {
+    if left.graph.states.len() != right.graph.states.len() || left.transitions.len() != right.transitions.len() {
+        return None;
+    }
+
+    let mut signatures: HashMap<Signature<S>, usize> = HashMap::new();
+    let mut right_signatures = vec![0; right.graph.ids];
+    let mut right_states: Vec<Vec<State>> = Vec::new();
+    for &state in &right.graph.states {
+        let next_signature = signatures.len();
+        let signature = *signatures.entry(right.signature(state)).or_insert(next_signature);
+        if signature == right_states.len() {
+            right_states.push(Vec::new());
+        }
+        right_signatures[state] = signature;
+        right_states[signature].push(state);
+    }
+
+    let mut left_signatures = vec![None; left.graph.ids];
+    left.graph.states.iter().for_each(|&state| left_signatures[state] = signatures.get(&left.signature(state)).copied());
+
+    let mut isomorphism = Isomorphism {
+        left,
+        right,
+        order: left.search_order(),
+        left_signatures,
+        right_signatures,
+        right_states,
+        mapping: vec![None; left.graph.ids],
+        inverse: vec![None; right.graph.ids],
+    };
+    isomorphism.search().then_some(isomorphism.mapping)
+}
+
+// Pairs the start states, then walks both automata at the same time pairing the states reached
+// through the same symbols. For DFAs this is the pairing of the states reached by the same strings.
+fn pair_greedily<S: Letter>(left: &Indexed<S>, right: &Indexed<S>) -> Vec<Option<State>> {
+    let mut mapping = vec![None; left.graph.ids];
+    let mut used = vec![false; right.graph.ids];
+    let mut pending = VecDeque::new();
+
+    let sorted_starts = |indexed: &Indexed<S>| {
+        let mut states: Vec<State> = indexed.graph.start_states.iter().copied().collect();
+        states.sort_by_key(|&state| (indexed.signature(state), state));
+        states
+    };
+    for (from, to) in sorted_starts(left).into_iter().zip(sorted_starts(right)) {
+        mapping[from] = Some(to);
+        used[to] = true;
+        pending.push_back((from, to));
+    }
+
+    while let Some((from, to)) = pending.pop_front() {
+        let mut symbols: Vec<Symbol<S>> = left.outgoing[from].iter().chain(&right.outgoing[to]).map(|(symbol, _)| symbol.clone()).collect();
+        symbols.sort();
+        symbols.dedup();
+
+        for symbol in symbols {
+            let left_next: Vec<State> = left.outgoing[from].iter()
+                .filter(|(other, next)| *other == symbol && mapping[*next].is_none())
+                .map(|(_, next)| *next)
+                .collect();
+            let right_next: Vec<State> = right.outgoing[to].iter()
+                .filter(|(other, next)| *other == symbol && !used[*next])
+                .map(|(_, next)| *next)
+                .collect();
+
+            for (left_next, right_next) in left_next.into_iter().zip(right_next) {
+                // a state can be reached more than once from the same state
+                if mapping[left_next].is_some() || used[right_next] {
+                    continue;
+                }
+                mapping[left_next] = Some(right_next);
+                used[right_next] = true;
+                pending.push_back((left_next, right_next));
+            }
+        }
+    }
+
+    mapping
+}
+
+/// draws the first automata with the differences: unmatched states in red when they are only in the
+/// first automata and in green when only in the second one, and the same for transitions. Paired
+/// states are labeled with both ids, in yellow when their acceptance or start differs.
+pub struct DiffVisualizer {
+    mermaid: String,
+}
+
+impl DiffVisualizer {
+    pub fn new<S: Letter + Display>(diff: &AutomataDiff<S>) -> DiffVisualizer {
+        let mut visualizer = DiffVisualizer { mermaid: String::new() };
+        let inverse: HashMap<State, State> = diff.mapping.iter().map(|&(left, right)| (right, left)).collect();
+        let mismatches: HashSet<State> = diff.acceptance_mismatches.iter()
+            .chain(&diff.start_mismatches)
+            .map(|&(left, _)| left)
+            .collect();
+
+        for &(left, right) in &diff.mapping {
+            visualizer.mermaid += &format!("\n        l{left}((\"{left} = {right}\"))");
+            if mismatches.contains(&left) {
+                visualizer.mermaid += &format!("\n        style l{left} fill:#ff6");
+            }
+        }
+        for &state in &diff.unmatched_left {
+            visualizer.mermaid += &format!("\n        l{state}((\"{state} = ?\"))\n        style l{state} fill:#f66");
+        }
+        for &state in &diff.unmatched_right {
+            visualizer.mermaid += &format!("\n        r{state}((\"? = {state}\"))\n        style r{state} fill:#9f9");
+        }
+
+        let mut links = 0;
+        for (from, symbol, to) in &diff.common_transitions {
+            visualizer.add_link(&format!("l{from}"), symbol, &format!("l{to}"));
+            links += 1;
+        }
+        for (from, symbol, to) in &diff.missing_transitions {
+            visualizer.add_link(&format!("l{from}"), symbol, &format!("l{to}"));
+            visualizer.mermaid += &format!("\n        linkStyle {links} stroke:#f00,stroke-width:3px");
+            links += 1;
+        }
+        let right_node = |state: State| match inverse.get(&state) {
+            Some(left) => format!("l{left}"),
+            None => format!("r{state}"),
+        };
+        for (from, symbol, to) in &diff.extra_transitions {
+            visualizer.add_link(&right_node(*from), symbol, &right_node(*to));
+            visualizer.mermaid += &format!("\n        linkStyle {links} stroke:#0a0,stroke-width:3px");
+            links += 1;
+        }
+
+        visualizer
+    }
+
+    fn add_link<S: Display>(&mut self, from: &str, symbol: &Symbol<S>, to: &str) {
+        match symbol {
+            Symbol::Character(c) => self.mermaid += &format!("\n        {from} -->|\"{c}\"| {to}"),
+            Symbol::Epsilon => self.mermaid += &format!("\n        {from} -->|ε| {to}"),
+        }
+    }
+
+    pub fn show(&self, path: &str) -> io::Result<String> {
+        self.generate_and_open_graph(path)
+    }
+}
+
+impl MermaidGraph for DiffVisualizer {
+    fn header(&self) -> &'static str {
+        "graph LR"
+    }
+
+    fn get_mermaid_content(&self) -> &str {
+        &self.mermaid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automata::dfa::DFAutomata;
+    use crate::automata::nfa::NFAutomata;
+
+    #[test]
+    fn dfa_diff() {
+        let direct = DFAutomata::try_from("(a|b)*abb").unwrap();
+        let thompson = DFAutomata::from(NFAutomata::try_from("(a|b)*abb").unwrap());
+        assert!(direct.is_isomorphic(&thompson));
+
+        let diff = direct.diff(&DFAutomata::try_from("(a|b)*ab").unwrap());
+        assert!(!diff.is_isomorphic());
+        assert_eq!(vec![(0, 0), (1, 1), (2, 2)], diff.mapping);
+        assert_eq!(vec![3], diff.unmatched_left);
+        assert_eq!(vec![(2, 2)], diff.acceptance_mismatches);
+        assert!(diff.missing_transitions.contains(&(2, Symbol::Character('b'), 3)));
+        assert!(diff.extra_transitions.contains(&(2, Symbol::Character('b'), 0)));
+
+        let mermaid = DiffVisualizer::new(&diff).mermaid;
+        assert!(mermaid.contains("style l3 fill:#f66"));
+        assert!(mermaid.contains("style l2 fill:#ff6"));
+    }
+
+    #[test]
+    fn nfa_diff() {
+        let ab = NFAutomata::literal("a").alternate(NFAutomata::literal("b"));
+        let ba = NFAutomata::literal("b").alternate(NFAutomata::literal("a"));
+        assert!(ab.is_isomorphic(&ba));
+        assert!(!ab.is_isomorphic(&NFAutomata::literal("a").alternate(NFAutomata::literal("c"))));
+
+        let diff = NFAutomata::literal("ab").diff(&NFAutomata::literal("ac"));
+        assert_eq!(vec![(0, 0), (1, 1)], diff.mapping);
+        assert_eq!((vec![2], vec![2]), (diff.unmatched_left, diff.unmatched_right));
+        assert_eq!(vec![(1, Symbol::Character('b'), 2)], diff.missing_transitions);
+        assert_eq!(vec![(1, Symbol::Character('c'), 2)], diff.extra_transitions);
+    }
+
+    #[test]
+    fn long_chains() {
+        let input = "a".repeat(50_000);
+        let nfa = NFAutomata::literal(&input);
+        // the states of the reversed chain are paired in the opposite order
+        assert!(nfa.is_isomorphic(&nfa.reverse()));
+        assert!(!nfa.is_isomorphic(&NFAutomata::literal(&input[1..]).reverse()));
+
+        let dfa = NFAutomata::literal(&input).into_determinate(false);
+        assert!(dfa.is_isomorphic(&nfa.into_determinate(true)));
+    }
+}
@@ -0,0 +1,75 @@
+use crate::BuildError;
+
+/// The biggest automata the constructions are allowed to build. Going over them stops the
+/// construction with an error, instead of running out of memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub dfa_states: usize,
+    pub nfa_states: usize,
+    /// applies to both NFAs and DFAs
+    pub transitions: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { dfa_states: 10_000, nfa_states: 100_000, transitions: 1_000_000 }
+    }
+}
+
+impl Limits {
+    pub fn unlimited() -> Limits {
+        Limits { dfa_states: usize::MAX, nfa_states: usize::MAX, transitions: usize::MAX }
+    }
+
+    pub(crate) fn check_dfa_states(&self, states: usize) -> Result<(), BuildError> {
+        if states > self.dfa_states {
+            return Err(BuildError::TooManyDFAStates(self.dfa_states));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_nfa_states(&self, states: usize) -> Result<(), BuildError> {
+        if states > self.nfa_states {
+            return Err(BuildError::TooManyNFAStates(self.nfa_states));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_transitions(&self, transitions: usize) -> Result<(), BuildError> {
+        if transitions > self.transitions {
+            return Err(BuildError::TooManyTransitions(self.transitions));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automata::dfa::DFAutomata;
+    use crate::automata::nfa::NFAutomata;
+    use crate::tree::LexTree;
+    use crate::automata::Inspect;
+
+    #[test]
+    fn limits() {
+        // the DFA of this expression has more than 2^20 states
+        let expression = format!("(a|b)*a{}", "(a|b)".repeat(20));
+        let tree = LexTree::try_from(expression.as_str()).unwrap();
+        let limits = Limits::default();
+
+        assert_eq!(Some(BuildError::TooManyDFAStates(10_000)), DFAutomata::build_with_limits(&tree, true, &limits).err());
+        let automata = NFAutomata::build_with_limits(&tree, &limits).unwrap();
+        let automata_size = (automata.state_count(), automata.transitions().len());
+        assert_eq!(Some(BuildError::TooManyDFAStates(10_000)), automata.into_determinate_with_limits(false, &limits).err());
+
+        let small = Limits { nfa_states: 50, ..Limits::default() };
+        assert_eq!(Some(BuildError::TooManyNFAStates(50)), NFAutomata::build_with_limits(&tree, &small).err());
+        let few_transitions = Limits { transitions: 5, ..Limits::default() };
+        assert_eq!(Some(BuildError::TooManyTransitions(5)), DFAutomata::build_with_limits(&tree, false, &few_transitions).err());
+        assert_eq!(Some(BuildError::TooManyTransitions(5)), NFAutomata::build_with_limits(&tree, &few_transitions).err());
+        // just enough for the automata
+        let exact = Limits { nfa_states: automata_size.0, transitions: automata_size.1, ..Limits::default() };
+        assert!(NFAutomata::build_with_limits(&tree, &exact).is_ok());
+    }
+}
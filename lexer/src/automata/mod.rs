@@ -1,8 +1,109 @@
+use crate::{Letter, Symbol};
+
 pub mod nfa;
 pub mod dfa;
+mod trace;
+mod limits;
+mod diff;
+
+pub use trace::TraceStep;
+pub use limits::Limits;
+pub use diff::{AutomataDiff, DiffVisualizer};
 
 type State = usize;
 
 pub trait Automata {
     fn test(&self, input: &str) -> bool;
 }
+
+/// Read-only view of the structure of an automata, so visualizers and analyses can be written once
+/// for any of them. The states are numbered from 0 to `state_count() - 1` unless the automata skips
+/// some ids, in which case `states()` says which ones it has. Every list is sorted.
+pub trait Inspect<S: Letter = char> {
+    fn state_count(&self) -> usize;
+
+    /// the ids of the states
+    fn states(&self) -> Vec<State> {
+        (0..self.state_count()).collect()
+    }
+
+    fn start_states(&self) -> Vec<State>;
+
+    fn is_accepting(&self, state: State) -> bool;
+
+    /// the transitions leaving a state, along with the state they go to
+    fn transitions_from(&self, state: State) -> Vec<(Symbol<S>, State)>;
+
+    fn acceptance_states(&self) -> Vec<State> {
+        self.states().into_iter().filter(|&state| self.is_accepting(state)).collect()
+    }
+
+    /// every transition as `(from, symbol, to)`
+    fn transitions(&self) -> Vec<(State, Symbol<S>, State)> {
+        self.states().into_iter()
+            .flat_map(|from| self.transitions_from(from).into_iter().map(move |(symbol, to)| (from, symbol, to)))
+            .collect()
+    }
+
+    /// the symbols the automata is defined over, by default the ones with transitions
+    fn alphabet(&self) -> Vec<S> {
+        let mut alphabet: Vec<S> = self.transitions().into_iter()
+            .filter_map(|(_, symbol, _)| match symbol {
+                Symbol::Character(c) => Some(c),
+                Symbol::Epsilon => None,
+            })
+            .collect();
+        alphabet.sort();
+        alphabet.dedup();
+        alphabet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::dfa::DFAutomata;
+    use super::nfa::NFAVisualizer;
+    use crate::mermaid_graph::MermaidGraph;
+
+    // an automata defined by hand, reading `word` over and over
+    struct Cycle {
+        word: Vec<char>,
+    }
+
+    impl Inspect for Cycle {
+        fn state_count(&self) -> usize {
+            self.word.len()
+        }
+
+        fn start_states(&self) -> Vec<State> {
+            vec![0]
+        }
+
+        fn is_accepting(&self, state: State) -> bool {
+            state == 0
+        }
+
+        fn transitions_from(&self, state: State) -> Vec<(Symbol, State)> {
+            vec![(Symbol::Character(self.word[state]), (state + 1) % self.word.len())]
+        }
+    }
+
+    #[test]
+    fn inspect() {
+        let cycle = Cycle { word: vec!['a', 'b'] };
+        assert_eq!(vec![0], cycle.acceptance_states());
+        assert_eq!(vec![(0, Symbol::Character('a'), 1), (1, Symbol::Character('b'), 0)], cycle.transitions());
+        assert_eq!(vec!['a', 'b'], cycle.alphabet());
+
+        let automata = DFAutomata::try_from("(ab)*").unwrap();
+        assert_eq!(cycle.transitions(), automata.transitions());
+        assert!(AutomataDiff::new(&cycle, &automata).is_isomorphic());
+        assert!(!AutomataDiff::new(&Cycle { word: vec!['a', 'b', 'b'] }, &automata).is_isomorphic());
+
+        assert_eq!(
+            NFAVisualizer::new(&automata).get_mermaid_content(),
+            NFAVisualizer::new(&cycle).get_mermaid_content(),
+        );
+    }
+}
@@ -1,79 +1,229 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use super::builder::NFABuilder;
-use crate::{LexError, Symbol};
+use super::simulation::Simulation;
+use crate::{BuildError, LexError, Letter, Symbol};
 use crate::automata::dfa::DFAOptimizer;
 use crate::tree::LexTree;
-use super::super::{State, Automata};
+use super::super::{State, Automata, AutomataDiff, Inspect, Limits, TraceStep};
 use super::super::dfa::DFAutomata;
 
-pub struct NFAutomata {
-    pub(super) transitions: HashMap<(State, Symbol), HashSet<State>>,
-    pub(super) acceptance_state: State,
+pub struct NFAutomata<S = char> {
+    // the transitions leaving each state, the states are the indexes
+    pub(super) transitions: Vec<Vec<(Symbol<S>, State)>>,
+    pub(super) start_states: HashSet<State>,
+    pub(super) acceptance_states: HashSet<State>,
+    // the ids without a state, as automata made by hand may skip some. They have no transitions
+    pub(super) skipped_ids: HashSet<State>,
 }
 
-impl NFAutomata {
-    fn epsilon_closure(&self, state: HashSet<State>) -> HashSet<State> {
-        let mut visited_states = state;
-        let mut new_states = self.movement(&visited_states, Symbol::Epsilon);
+impl<S: Letter> NFAutomata<S> {
+    pub(crate) fn new(transitions: Vec<Vec<(Symbol<S>, State)>>, start_states: HashSet<State>, acceptance_states: HashSet<State>) -> NFAutomata<S> {
+        NFAutomata { transitions, start_states, acceptance_states, skipped_ids: HashSet::new() }
+    }
+
+    // one more than the highest id, the size of anything indexed by the states
+    pub(super) fn id_bound(&self) -> usize {
+        self.transitions.len()
+    }
+
+    /// an automata accepting the reversed strings of this one. The acceptance states become the
+    /// start states and the other way around.
+    pub fn reverse(&self) -> NFAutomata<S> {
+        let mut transitions = vec![Vec::new(); self.id_bound()];
 
-        while !new_states.is_empty() {
-            visited_states.extend(&new_states);
-            new_states = &self.movement(&new_states, Symbol::Epsilon) - &visited_states;
+        for (from, edges) in self.transitions.iter().enumerate() {
+            for (symbol, to) in edges {
+                transitions[*to].push((symbol.clone(), from));
+            }
         }
 
-        visited_states
+        NFAutomata {
+            transitions,
+            start_states: self.acceptance_states.clone(),
+            acceptance_states: self.start_states.clone(),
+            skipped_ids: self.skipped_ids.clone(),
+        }
     }
 
-    fn movement(&self, states: &HashSet<State>, symbol: Symbol) -> HashSet<State> {
-        let mut result_states = HashSet::new();
+    /// finds a renaming of the states making both automata the same, otherwise reports what differs
+    /// between them
+    pub fn diff(&self, other: &NFAutomata<S>) -> AutomataDiff<S> {
+        AutomataDiff::new(self, other)
+    }
 
-        states.iter()
-            .filter_map(|&state| self.single_movement(state, symbol))
-            .for_each(|new_states| result_states.extend(new_states));
+    pub fn is_isomorphic(&self, other: &NFAutomata<S>) -> bool {
+        self.diff(other).is_isomorphic()
+    }
 
-        result_states
+    /// an equivalent automata without ε transitions. Every state gets the transitions of its
+    /// ε-closure, and the states only reachable through ε transitions are dropped.
+    pub fn remove_epsilons(&self) -> NFAutomata<S> {
+        let mut transitions = Vec::with_capacity(self.id_bound());
+        let mut acceptance_states = HashSet::new();
+
+        for state in 0..self.id_bound() {
+            let closure = self.epsilon_closure(&[state]);
+            if closure.iter().any(|state| self.acceptance_states.contains(state)) {
+                acceptance_states.insert(state);
+            }
+
+            let mut edges: Vec<(Symbol<S>, State)> = closure.iter()
+                .flat_map(|&state| &self.transitions[state])
+                .filter(|(symbol, _)| *symbol != Symbol::Epsilon)
+                .cloned()
+                .collect();
+            edges.sort();
+            edges.dedup();
+            transitions.push(edges);
+        }
+
+        // keep the reachable states, numbered in the order they had. The skipped ids are never reached
+        let mut reachable = vec![false; self.id_bound()];
+        let mut pending: Vec<State> = self.start_states.iter().copied().collect();
+        pending.iter().for_each(|&state| reachable[state] = true);
+        while let Some(state) = pending.pop() {
+            for &(_, to) in &transitions[state] {
+                if !reachable[to] {
+                    reachable[to] = true;
+                    pending.push(to);
+                }
+            }
+        }
+
+        let mut new_ids = vec![0; self.id_bound()];
+        let mut next_id = 0;
+        for state in 0..self.id_bound() {
+            new_ids[state] = next_id;
+            next_id += reachable[state] as usize;
+        }
+
+        NFAutomata {
+            transitions: transitions.into_iter()
+                .enumerate()
+                .filter(|(state, _)| reachable[*state])
+                .map(|(_, edges)| edges.into_iter().map(|(symbol, to)| (symbol, new_ids[to])).collect())
+                .collect(),
+            start_states: self.start_states.iter().map(|&state| new_ids[state]).collect(),
+            acceptance_states: acceptance_states.into_iter()
+                .filter(|&state| reachable[state])
+                .map(|state| new_ids[state])
+                .collect(),
+            skipped_ids: HashSet::new(),
+        }
     }
 
-    fn single_movement(&self, state: State, symbol: Symbol) -> Option<&HashSet<State>>  {
-        self.transitions.get(&(state, symbol))
+    // the states are kept sorted so equal sets are equal vectors
+    pub(super) fn epsilon_closure(&self, states: &[State]) -> Vec<State> {
+        let mut visited = vec![false; self.id_bound()];
+        let mut pending = states.to_vec();
+        let mut closure = Vec::new();
+
+        pending.iter().for_each(|&state| visited[state] = true);
+        while let Some(state) = pending.pop() {
+            closure.push(state);
+            for (symbol, to) in &self.transitions[state] {
+                if *symbol == Symbol::Epsilon && !visited[*to] {
+                    visited[*to] = true;
+                    pending.push(*to);
+                }
+            }
+        }
+
+        closure.sort();
+        closure
     }
 
-    pub fn into_determinate(self, optimized: bool) -> DFAutomata {
-        let chars_with_transitions: Vec<char> = self.transitions
-            .keys()
-            .filter_map(|(_, x)| if let Symbol::Character(x) = x { Some(*x) } else { None })
+    pub(super) fn movement(&self, states: &[State], symbol: &Symbol<S>) -> Vec<State> {
+        let mut result_states: Vec<State> = states.iter()
+            .flat_map(|&state| self.single_movement(state, symbol))
             .collect();
 
+        result_states.sort();
+        result_states.dedup();
+        result_states
+    }
+
+    fn single_movement<'a>(&'a self, state: State, symbol: &'a Symbol<S>) -> impl Iterator<Item=State> + 'a {
+        self.transitions[state].iter()
+            .filter(move |(other, _)| other == symbol)
+            .map(|&(_, to)| to)
+    }
+
+    pub(super) fn start_closure(&self) -> Vec<State> {
+        let start_states: Vec<State> = self.start_states.iter().copied().collect();
+        self.epsilon_closure(&start_states)
+    }
+
+    pub(super) fn accepts(&self, states: &[State]) -> bool {
+        states.iter().any(|state| self.acceptance_states.contains(state))
+    }
+
+    /// whether the automata accepts the sequence of symbols
+    pub fn test_symbols(&self, input: impl IntoIterator<Item=S>) -> bool {
+        let mut simulation = Simulation::new(self);
+
+        for symbol in input {
+            simulation.step(&symbol);
+            if simulation.is_dead() {
+                return false;
+            }
+        }
+
+        simulation.is_accepting()
+    }
+
+    pub fn into_determinate(self, optimized: bool) -> DFAutomata<S> {
+        self.into_determinate_with_limits(optimized, &Limits::unlimited())
+            .expect("there are no limits")
+    }
+
+    /// the subset construction, stopping as soon as the DFA gets bigger than the limits
+    pub fn into_determinate_with_limits(self, optimized: bool, limits: &Limits) -> Result<DFAutomata<S>, BuildError> {
+        let symbols_with_transitions: Vec<S> = self.transitions
+            .iter()
+            .flatten()
+            .filter_map(|(x, _)| if let Symbol::Character(x) = x { Some(x.clone()) } else { None })
+            .collect();
 
         let mut acceptance_states = HashSet::new();
         let mut transitions = HashMap::new();
         let mut current_state_id = 0;
 
-        let mut known_states = vec![
-            self.epsilon_closure(HashSet::from([0]))
-        ];
+        let start_state = self.start_closure();
+        let mut state_ids: HashMap<Vec<State>, State> = HashMap::from([(start_state.clone(), 0)]);
+        let mut known_states = vec![start_state];
 
         loop {
-            let current_state = &known_states[current_state_id].clone();
+            let current_state = &known_states[current_state_id];
 
-            if current_state.contains(&self.acceptance_state) {
+            if self.accepts(current_state) {
                 acceptance_states.insert(current_state_id);
             }
 
-            for x in &chars_with_transitions {
-                let new_state = self.epsilon_closure(self.movement(current_state, Symbol::Character(*x)));
-                if new_state.is_empty() {
-                    continue;
+            // the states reached through each symbol, in order so the numbering is always the same
+            let mut movements: BTreeMap<&S, Vec<State>> = BTreeMap::new();
+            for &from in current_state {
+                for (symbol, to) in &self.transitions[from] {
+                    if let Symbol::Character(x) = symbol {
+                        movements.entry(x).or_default().push(*to);
+                    }
                 }
+            }
+
+            for (x, mut states) in movements {
+                // states sharing a target reach it more than once
+                states.sort();
+                states.dedup();
+                let new_state = self.epsilon_closure(&states);
 
-                let to = known_states.iter()
-                    .position(|other| other == &new_state)
-                    .unwrap_or_else(|| {
-                        known_states.push(new_state);
-                        known_states.len() - 1
-                    });
+                let to = *state_ids.entry(new_state).or_insert_with_key(|new_state| {
+                    known_states.push(new_state.clone());
+                    known_states.len() - 1
+                });
+                limits.check_dfa_states(known_states.len())?;
 
-                transitions.insert((current_state_id, *x), to);
+                transitions.insert((current_state_id, x.clone()), to);
+                limits.check_transitions(transitions.len())?;
             }
 
             current_state_id += 1;
@@ -83,36 +233,93 @@ impl NFAutomata {
         }
 
         if optimized {
-            DFAOptimizer::optimize(transitions, acceptance_states, current_state_id-1, chars_with_transitions)
+            Ok(DFAOptimizer::optimize(transitions, acceptance_states, current_state_id-1, symbols_with_transitions))
         } else {
-            DFAutomata::new(transitions, acceptance_states, current_state_id-1)
+            Ok(DFAutomata::new(transitions, acceptance_states, current_state_id-1)
+                .with_subsets(known_states))
         }
     }
 }
 
+impl NFAutomata {
+    /// the automata of the Thompson construction, stopping as soon as it gets bigger than the limits
+    pub fn build_with_limits(node: &LexTree, limits: &Limits) -> Result<NFAutomata, BuildError> {
+        Ok(NFABuilder::build_with_limits(node, limits)?.into_thompson_automata())
+    }
+
+    pub fn trace(&self, input: &str) -> Vec<TraceStep> {
+        let mut states = self.start_closure();
+        let mut steps = vec![self.trace_step(None, &[], &states)];
+
+        for c in input.chars() {
+            let previous = states;
+            states = self.epsilon_closure(&self.movement(&previous, &Symbol::Character(c)));
+            steps.push(self.trace_step(Some(c), &previous, &states));
+
+            if states.is_empty() {
+                break;
+            }
+        }
+
+        steps
+    }
+
+    fn trace_step(&self, input: Option<char>, previous: &[State], states: &[State]) -> TraceStep {
+        let mut transitions = Vec::new();
+
+        if let Some(c) = input {
+            for &from in previous {
+                self.single_movement(from, &Symbol::Character(c))
+                    .for_each(|to| transitions.push((from, Symbol::Character(c), to)));
+            }
+        }
+
+        // every ε transition leaving an active state was followed by the closure
+        for &from in states {
+            self.single_movement(from, &Symbol::Epsilon)
+                .for_each(|to| transitions.push((from, Symbol::Epsilon, to)));
+        }
+        transitions.sort_by_key(|&(from, _, to)| (from, to));
+
+        TraceStep { input, active_states: states.to_vec(), transitions }
+    }
+}
+
 impl Automata for NFAutomata {
     fn test(&self, input: &str) -> bool {
+        self.test_symbols(input.chars())
+    }
+}
 
-        let final_states = input
-            .chars()
-            .fold(
-                self.epsilon_closure(HashSet::from([0])),
-                |states, c| {
-                    self.epsilon_closure(self.movement(&states, Symbol::Character(c)))
-            });
+impl<S: Letter> Inspect<S> for NFAutomata<S> {
+    fn state_count(&self) -> usize {
+        self.transitions.len() - self.skipped_ids.len()
+    }
 
-        final_states.contains(&self.acceptance_state)
+    fn states(&self) -> Vec<State> {
+        (0..self.id_bound()).filter(|state| !self.skipped_ids.contains(state)).collect()
+    }
+
+    fn start_states(&self) -> Vec<State> {
+        let mut start_states: Vec<State> = self.start_states.iter().copied().collect();
+        start_states.sort();
+        start_states
+    }
+
+    fn is_accepting(&self, state: State) -> bool {
+        self.acceptance_states.contains(&state)
+    }
+
+    fn transitions_from(&self, state: State) -> Vec<(Symbol<S>, State)> {
+        let mut edges = self.transitions[state].clone();
+        edges.sort();
+        edges
     }
 }
 
 impl From<&LexTree> for NFAutomata {
     fn from(value: &LexTree) -> Self {
-        let builder = NFABuilder::build(value);
-
-        NFAutomata {
-            transitions: builder.transitions,
-            acceptance_state: builder.last_state,
-        }
+        NFABuilder::build(value).into_thompson_automata()
     }
 }
 
@@ -121,12 +328,7 @@ impl TryFrom<&str> for NFAutomata {
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         let node = LexTree::try_from(value)?;
-        let builder = NFABuilder::build(&node);
-
-        Ok(NFAutomata {
-            transitions: builder.transitions,
-            acceptance_state: builder.last_state,
-        })
+        Ok(NFABuilder::build(&node).into_thompson_automata())
     }
 }
 
@@ -139,4 +341,155 @@ mod tests {
         let automata = NFAutomata::try_from("a").unwrap();
         assert!(automata.test("a"))
     }
+
+    #[test]
+    fn reverse() {
+        let automata = NFAutomata::try_from("ab*c").unwrap().reverse();
+
+        assert!(automata.test("cbba"));
+        assert!(automata.test("ca"));
+        assert!(!automata.test("abc"));
+    }
+
+    #[test]
+    fn trace() {
+        let automata = NFAutomata::try_from("a*b").unwrap();
+        let trace = automata.trace("ab");
+
+        assert_eq!(3, trace.len());
+        assert_eq!(None, trace[0].input);
+        assert_eq!(Some('b'), trace[2].input);
+        assert!(trace[2].active_states.iter().any(|state| automata.acceptance_states.contains(state)));
+        assert!(trace[1].transitions.contains(&(1, Symbol::Character('a'), 2)));
+    }
+
+    #[test]
+    fn remove_epsilons() {
+        let automata = NFAutomata::try_from("(a|b)*abb(c?d)*").unwrap();
+        let without_epsilons = automata.remove_epsilons();
+
+        assert!(without_epsilons.transitions.iter().flatten().all(|(symbol, _)| *symbol != Symbol::Epsilon));
+        assert!(without_epsilons.state_count() < automata.state_count());
+        for input in ["abb", "babbd", "abbcdd", "ab", "abbc", ""] {
+            assert_eq!(automata.test(input), without_epsilons.test(input));
+        }
+    }
+
+    #[test]
+    fn determinize_without_epsilons() {
+        for expression in ["a*a*", "a*(a|b)*a"] {
+            let automata = NFAutomata::try_from(expression).unwrap();
+            let dfa = automata.remove_epsilons().into_determinate(false);
+
+            for input in ["", "a", "aa", "ab", "aba", "abba"] {
+                assert_eq!(automata.test(input), dfa.test(input));
+            }
+        }
+    }
+
+    #[test]
+    fn determinize_shared_targets() {
+        let mut builder = NFABuilder::new();
+        let (a, b) = (builder.add_state(), builder.add_state());
+        builder.add_transition(a, a, Symbol::Character('a')).unwrap();
+        builder.add_transition(a, b, Symbol::Character('a')).unwrap();
+        builder.add_transition(b, a, Symbol::Character('a')).unwrap();
+        let automata = builder.into_automata([a], [b]).unwrap().into_determinate(false);
+
+        assert_eq!(vec![vec![0], vec![0, 1]], automata.subsets().unwrap());
+        assert!(!automata.test(""));
+        assert!(automata.test("aaa"));
+    }
+
+    // cargo test --release -- --ignored --nocapture simulation_speed
+    #[test]
+    #[ignore]
+    fn simulation_speed() {
+        // how the automata was simulated before, computing new sets of states on every step
+        let with_sets = |automata: &NFAutomata, input: &str| {
+            let final_states = input.chars().fold(automata.start_closure(), |states, c| {
+                automata.epsilon_closure(&automata.movement(&states, &Symbol::Character(c)))
+            });
+            automata.accepts(&final_states)
+        };
+
+        let expression = format!("(a|b)*a{}", "(a|b)".repeat(200));
+        let automata = NFAutomata::try_from(expression.as_str()).unwrap();
+        let without_epsilons = automata.remove_epsilons();
+
+        for length in [1000, 10000, 100000] {
+            let input = "ab".repeat(length / 2) + "a" + &"b".repeat(200);
+
+            let start = std::time::Instant::now();
+            assert!(with_sets(&automata, &input));
+            let sets = start.elapsed();
+
+            let start = std::time::Instant::now();
+            assert!(automata.test(&input));
+            let simulation = start.elapsed();
+
+            let start = std::time::Instant::now();
+            assert!(without_epsilons.test(&input));
+            let simulation_without_epsilons = start.elapsed();
+
+            println!("{length} chars: sets {sets:?}, simulation {simulation:?}, without ε {simulation_without_epsilons:?}");
+        }
+    }
+
+    // cargo test --release -- --ignored --nocapture representation_size
+    #[test]
+    #[ignore]
+    fn representation_size() {
+        use std::mem::size_of;
+        type Edge = (Symbol, State);
+
+        // the bytes a hash table with this capacity allocates, with its buckets and a control byte each
+        fn table_size<T>(capacity: usize) -> usize {
+            if capacity == 0 {
+                return 0;
+            }
+            let buckets = (capacity * 8 / 7).next_power_of_two().max(4);
+            buckets * (size_of::<T>() + 1) + 16
+        }
+
+        // how the transitions were kept before, with an ε self-loop on every state
+        let with_sets = |automata: &NFAutomata| {
+            let mut transitions: HashMap<(State, Symbol), HashSet<State>> = HashMap::new();
+            for (from, edges) in automata.transitions.iter().enumerate() {
+                transitions.entry((from, Symbol::Epsilon)).or_default().insert(from);
+                for &(symbol, to) in edges {
+                    transitions.entry((from, symbol)).or_default().insert(to);
+                }
+            }
+            transitions
+        };
+        let sets_size = |transitions: &HashMap<(State, Symbol), HashSet<State>>| {
+            table_size::<((State, Symbol), HashSet<State>)>(transitions.capacity())
+                + transitions.values().map(|states| table_size::<State>(states.capacity())).sum::<usize>()
+        };
+        let lists_size = |automata: &NFAutomata| {
+            automata.transitions.capacity() * size_of::<Vec<Edge>>()
+                + automata.transitions.iter().map(|edges| edges.capacity() * size_of::<Edge>()).sum::<usize>()
+        };
+
+        let words: Vec<String> = (0..2000).map(|i| format!("w{i}x")).collect();
+        let expressions = [
+            format!("(a|b)*a{}", "(a|b)".repeat(200)),
+            format!("({})*", words.join("|")),
+        ];
+
+        for expression in expressions {
+            let automata = NFAutomata::try_from(expression.as_str()).unwrap();
+            let without_epsilons = automata.remove_epsilons();
+
+            println!(
+                "{} states: sets {} KiB, adjacency lists {} KiB, without ε {} KiB ({} states)",
+                automata.state_count(),
+                sets_size(&with_sets(&automata)) / 1024,
+                lists_size(&automata) / 1024,
+                lists_size(&without_epsilons) / 1024,
+                without_epsilons.state_count(),
+            );
+        }
+    }
 }
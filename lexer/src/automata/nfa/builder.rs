@@ -1,109 +1,258 @@
-use std::collections::{HashMap, HashSet};
-use crate::{UnaryOperator, BinaryOperator, Symbol};
+use std::collections::HashSet;
+use crate::{UnaryOperator, BinaryOperator, Letter, Symbol, BuildError, Visitable, Visitor};
 use super::super::super::tree::LexTree;
-use super::super::State;
+use super::super::{Limits, State};
+use super::automata::NFAutomata;
 
 
-pub struct NFABuilder {
-    pub last_state: State,
-    pub transitions: HashMap<(State, Symbol), HashSet<State>>,
+pub struct NFABuilder<S = char> {
+    // the transitions leaving each state, the states are the indexes
+    pub(super) transitions: Vec<Vec<(Symbol<S>, State)>>,
+    // whether each id belongs to a state, as states made by hand may skip some ids
+    defined: Vec<bool>,
+    transition_count: usize,
+    // the builders made by hand or by the combinators have no limits
+    limits: Limits,
+}
+
+impl<S: Letter> Default for NFABuilder<S> {
+    fn default() -> Self {
+        NFABuilder::new()
+    }
+}
+
+impl<S: Letter> NFABuilder<S> {
+    /// a builder for an automata made by hand, without any states
+    pub fn new() -> NFABuilder<S> {
+        NFABuilder { transitions: Vec::new(), defined: Vec::new(), transition_count: 0, limits: Limits::unlimited() }
+    }
+
+    pub fn add_state(&mut self) -> State {
+        self.create_state().expect("there are no limits")
+    }
+
+    pub fn add_state_with_id(&mut self, state: State) -> Result<State, BuildError> {
+        if self.is_defined(state) {
+            return Err(BuildError::DuplicateState(state));
+        }
+
+        if state >= self.transitions.len() {
+            self.transitions.resize(state + 1, Vec::new());
+            self.defined.resize(state + 1, false);
+        }
+        self.defined[state] = true;
+        Ok(state)
+    }
+
+    pub fn add_transition(&mut self, from: State, to: State, symbol: Symbol<S>) -> Result<(), BuildError> {
+        self.check_states([from, to])?;
+        self.connect(from, to, symbol)
+    }
+
+    /// the states keep the ids they were given, even when some ids were skipped
+    pub fn into_automata(self, start_states: impl IntoIterator<Item=State>, acceptance_states: impl IntoIterator<Item=State>) -> Result<NFAutomata<S>, BuildError> {
+        let start_states: HashSet<State> = start_states.into_iter().collect();
+        let acceptance_states: HashSet<State> = acceptance_states.into_iter().collect();
+        self.check_states(start_states.iter().chain(&acceptance_states).copied())?;
+
+        Ok(self.finish(start_states, acceptance_states))
+    }
+
+    // Thompson's constructions start on the root and end on the last created state
+    pub(super) fn into_thompson_automata(self) -> NFAutomata<S> {
+        let acceptance_state = self.transitions.len() - 1;
+        self.finish(HashSet::from([0]), HashSet::from([acceptance_state]))
+    }
+
+    pub(super) fn finish(self, start_states: HashSet<State>, acceptance_states: HashSet<State>) -> NFAutomata<S> {
+        let mut automata = NFAutomata::new(self.transitions, start_states, acceptance_states);
+        automata.skipped_ids = (0..self.defined.len()).filter(|&state| !self.defined[state]).collect();
+        automata
+    }
+
+    fn is_defined(&self, state: State) -> bool {
+        self.defined.get(state).copied().unwrap_or(false)
+    }
+
+    fn check_states(&self, states: impl IntoIterator<Item=State>) -> Result<(), BuildError> {
+        match states.into_iter().find(|&state| !self.is_defined(state)) {
+            Some(state) => Err(BuildError::UnknownState(state)),
+            None => Ok(()),
+        }
+    }
+
+    // copies the automata into this one, returning where its start and acceptance states ended up
+    pub(super) fn embed(&mut self, automata: &NFAutomata<S>) -> (HashSet<State>, HashSet<State>) {
+        let offset = self.transitions.len();
+
+        for (state, edges) in automata.transitions.iter().enumerate() {
+            self.transitions.push(edges.iter().map(|(symbol, to)| (symbol.clone(), to + offset)).collect());
+            self.defined.push(!automata.skipped_ids.contains(&state));
+            self.transition_count += edges.len();
+        }
+
+        let start_states = automata.start_states.iter().map(|state| state + offset).collect();
+        let acceptance_states = automata.acceptance_states.iter().map(|state| state + offset).collect();
+        (start_states, acceptance_states)
+    }
+
+    pub(super) fn connect(&mut self, from: State, to: State, symbol: Symbol<S>) -> Result<(), BuildError> {
+        let edge = (symbol, to);
+        if !self.transitions[from].contains(&edge) {
+            self.limits.check_transitions(self.transition_count + 1)?;
+            self.transitions[from].push(edge);
+            self.transition_count += 1;
+        }
+        Ok(())
+    }
+
+    pub(super) fn create_state(&mut self) -> Result<State, BuildError> {
+        self.limits.check_nfa_states(self.transitions.len() + 1)?;
+        self.transitions.push(Vec::new());
+        self.defined.push(true);
+        Ok(self.transitions.len() - 1)
+    }
 }
 
 impl NFABuilder {
     pub fn build(node: &LexTree) -> NFABuilder {
-        let mut builder = NFABuilder { transitions: HashMap::new(), last_state: 0 };
+        NFABuilder::build_with_limits(node, &Limits::unlimited())
+            .expect("there are no limits")
+    }
+
+    /// stops the construction as soon as the automata gets bigger than the limits
+    pub fn build_with_limits(node: &LexTree, limits: &Limits) -> Result<NFABuilder, BuildError> {
+        let mut builder = NFABuilder { limits: *limits, ..NFABuilder::new() };
 
         // create the root state
-        let root_state = builder.create_root();
-        builder.build_automata(node, root_state);
-        builder
+        let root_state = builder.create_root()?;
+        builder.build_automata(node, root_state)?;
+        Ok(builder)
+    }
+
+    fn create_root(&mut self) -> Result<State, BuildError> {
+        self.create_state()
     }
 
-    fn connect(&mut self, from: State, to: State, symbol: Symbol) {
-        if let Some(destination_states) = self.transitions.get_mut(&(from, symbol)) {
-            destination_states.insert(to);
-        } else {
-            self.transitions.insert((from, symbol), HashSet::from([to]));
+    fn build_automata(&mut self, node: &LexTree, starting_state: State) -> Result<State, BuildError> {
+        let mut thompson = Thompson { builder: self, starts: vec![starting_state], error: None };
+        let (_, end) = node.accept(&mut thompson);
+
+        match thompson.error {
+            Some(err) => Err(err),
+            None => Ok(end),
         }
     }
+}
+
+// Thompson's construction, the value of every node being the start and end states of its automata
+struct Thompson<'a> {
+    builder: &'a mut NFABuilder,
+    // the state the automata of each node being visited starts on
+    starts: Vec<State>,
+    // once the limits are exceeded the visit stops, leaving the rest of the tree unvisited
+    error: Option<BuildError>,
+}
+
+impl Visitor<LexTree> for Thompson<'_> {
+    type Value = (State, State);
 
-    fn create_state(&mut self) -> State {
-        self.last_state += 1;
-        self.connect(self.last_state, self.last_state, Symbol::Epsilon);
-        self.last_state
+    fn before_child(&mut self, parent: &LexTree, index: usize, siblings: &[(State, State)]) {
+        let starting_state = *self.starts.last().expect("the parent is being visited");
+
+        let child_start = match parent {
+            // the right automata starts where the left one ends
+            LexTree::Binary { value: BinaryOperator::Concat, .. } if index == 1 => Ok(siblings[0].1),
+            LexTree::Binary { value: BinaryOperator::Or, .. } |
+            LexTree::Unary { value: UnaryOperator::Kleene, .. } => self.builder.create_state(),
+            _ => Ok(starting_state),
+        };
+        let child_start = child_start.unwrap_or_else(|err| {
+            self.error = Some(err);
+            starting_state
+        });
+        self.starts.push(child_start);
     }
 
-    fn create_root(&mut self) -> State {
-        self.connect(self.last_state, self.last_state, Symbol::Epsilon);
-        self.last_state
+    fn leave(&mut self, node: &LexTree, children: Vec<(State, State)>) -> (State, State) {
+        let starting_state = self.starts.pop().expect("the node is being visited");
+        // after an error the nodes left are missing some children
+        if self.error.is_some() {
+            return (starting_state, starting_state);
+        }
+
+        match self.construct(node, starting_state, children) {
+            Ok(end) => (starting_state, end),
+            Err(err) => {
+                self.error = Some(err);
+                (starting_state, starting_state)
+            },
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.error.is_some()
     }
+}
 
-    fn build_automata(&mut self, node: &LexTree, starting_state: State) -> State {
-        match node {
+impl Thompson<'_> {
+    fn construct(&mut self, node: &LexTree, starting_state: State, children: Vec<(State, State)>) -> Result<State, BuildError> {
+        let builder = &mut *self.builder;
+
+        let end = match node {
             LexTree::Leaf { value } => {
-                let next_state = self.create_state();
-                self.connect(starting_state, next_state, *value);
+                let next_state = builder.create_state()?;
+                builder.connect(starting_state, next_state, *value)?;
 
                 next_state
-            }
-
-            LexTree::Binary { value: operator, right_child: right_node, left_child: left_node } => {
-                match operator {
-                    BinaryOperator::Concat => {
-                        let connection_state = self.build_automata(left_node, starting_state);
-                        self.build_automata(right_node, connection_state)
-                    },
-                    BinaryOperator::Or => {
-                        let top_start = self.create_state();
-                        let top_end = self.build_automata(left_node, top_start);
-
-                        let bottom_start = self.create_state();
-                        let bottom_end = self.build_automata(right_node, bottom_start);
-
-                        // connect to the start of both automatas
-                        self.connect(starting_state, top_start, Symbol::Epsilon);
-                        self.connect(starting_state, bottom_start, Symbol::Epsilon);
-
-                        // connect the end of both automatas
-                        let end_state = self.create_state();
-                        self.connect(top_end, end_state, Symbol::Epsilon);
-                        self.connect(bottom_end, end_state, Symbol::Epsilon);
-
-                        end_state
-                    }
-                }
             },
 
-            LexTree::Unary { value: operator, child } => {
-                match operator {
-                    UnaryOperator::Kleene => {
-                        let next_start = self.create_state();
-                        let next_end = self.build_automata(child, next_start);
-                        let end = self.create_state();
+            LexTree::Binary { value: BinaryOperator::Concat, .. } => children[1].1,
+
+            LexTree::Binary { value: BinaryOperator::Or, .. } => {
+                let [(top_start, top_end), (bottom_start, bottom_end)] = children[..] else {
+                    unreachable!("binary nodes have two children")
+                };
 
-                        self.connect(starting_state, end, Symbol::Epsilon);
-                        self.connect(starting_state, next_start, Symbol::Epsilon);
-                        self.connect(next_end, next_start, Symbol::Epsilon);
-                        self.connect(next_end, end, Symbol::Epsilon);
+                // connect to the start of both automatas
+                builder.connect(starting_state, top_start, Symbol::Epsilon)?;
+                builder.connect(starting_state, bottom_start, Symbol::Epsilon)?;
 
-                        end
-                    },
+                // connect the end of both automatas
+                let end_state = builder.create_state()?;
+                builder.connect(top_end, end_state, Symbol::Epsilon)?;
+                builder.connect(bottom_end, end_state, Symbol::Epsilon)?;
 
-                    UnaryOperator::Maybe => {
-                        let end = self.build_automata(child, starting_state);
-                        self.connect(starting_state, end, Symbol::Epsilon);
+                end_state
+            },
 
-                        end
-                    }
+            LexTree::Unary { value: UnaryOperator::Kleene, .. } => {
+                let (next_start, next_end) = children[0];
+                let end = builder.create_state()?;
 
-                    UnaryOperator::Many => {
-                        let end = self.build_automata(child, starting_state);
-                        self.connect(end, starting_state, Symbol::Epsilon);
+                builder.connect(starting_state, end, Symbol::Epsilon)?;
+                builder.connect(starting_state, next_start, Symbol::Epsilon)?;
+                builder.connect(next_end, next_start, Symbol::Epsilon)?;
+                builder.connect(next_end, end, Symbol::Epsilon)?;
 
-                        end
-                    }
-                }
-            }
-        }
+                end
+            },
+
+            LexTree::Unary { value: UnaryOperator::Maybe, .. } => {
+                let (_, end) = children[0];
+                builder.connect(starting_state, end, Symbol::Epsilon)?;
+
+                end
+            },
+
+            LexTree::Unary { value: UnaryOperator::Many, .. } => {
+                let (_, end) = children[0];
+                builder.connect(end, starting_state, Symbol::Epsilon)?;
+
+                end
+            },
+        };
+
+        Ok(end)
     }
 }
@@ -0,0 +1,163 @@
+use std::collections::HashSet;
+use crate::{Letter, Symbol};
+use super::automata::NFAutomata;
+use super::builder::NFABuilder;
+
+// Thompson's constructions over automata instead of LexTrees. Having many start and acceptance
+// states, most of them don't need any new states.
+impl<S: Letter> NFAutomata<S> {
+    /// accepts only the empty string
+    pub fn epsilon() -> NFAutomata<S> {
+        let mut builder = NFABuilder::new();
+        let state = builder.add_state();
+
+        builder.finish(HashSet::from([state]), HashSet::from([state]))
+    }
+
+    /// accepts only the symbols in this order
+    pub fn sequence(symbols: impl IntoIterator<Item=S>) -> NFAutomata<S> {
+        let mut builder = NFABuilder::new();
+        let start = builder.add_state();
+        let mut end = start;
+        for symbol in symbols {
+            let next = builder.add_state();
+            builder.connect(end, next, Symbol::Character(symbol)).expect("there are no limits");
+            end = next;
+        }
+
+        builder.finish(HashSet::from([start]), HashSet::from([end]))
+    }
+
+    /// accepts any single symbol of the class
+    pub fn class(symbols: impl IntoIterator<Item=S>) -> NFAutomata<S> {
+        let mut builder = NFABuilder::new();
+        let start = builder.add_state();
+        let end = builder.add_state();
+        for c in symbols {
+            builder.connect(start, end, Symbol::Character(c)).expect("there are no limits");
+        }
+
+        builder.finish(HashSet::from([start]), HashSet::from([end]))
+    }
+
+    pub fn concat(self, other: NFAutomata<S>) -> NFAutomata<S> {
+        let mut builder = NFABuilder::new();
+        let (start_states, end_states) = builder.embed(&self);
+        let (other_start_states, other_end_states) = builder.embed(&other);
+
+        for &end in &end_states {
+            other_start_states.iter().for_each(|&start| builder.connect(end, start, Symbol::Epsilon).expect("there are no limits"));
+        }
+
+        builder.finish(start_states, other_end_states)
+    }
+
+    pub fn alternate(self, other: NFAutomata<S>) -> NFAutomata<S> {
+        let mut builder = NFABuilder::new();
+        let (mut start_states, mut end_states) = builder.embed(&self);
+        let (other_start_states, other_end_states) = builder.embed(&other);
+
+        start_states.extend(other_start_states);
+        end_states.extend(other_end_states);
+        builder.finish(start_states, end_states)
+    }
+
+    pub fn star(self) -> NFAutomata<S> {
+        self.plus().optional()
+    }
+
+    pub fn plus(self) -> NFAutomata<S> {
+        let mut builder = NFABuilder::new();
+        let (start_states, end_states) = builder.embed(&self);
+
+        for &end in &end_states {
+            start_states.iter().for_each(|&start| builder.connect(end, start, Symbol::Epsilon).expect("there are no limits"));
+        }
+
+        builder.finish(start_states, end_states)
+    }
+
+    pub fn optional(self) -> NFAutomata<S> {
+        self.alternate(NFAutomata::epsilon())
+    }
+}
+
+impl NFAutomata {
+    pub fn literal(value: &str) -> NFAutomata {
+        NFAutomata::sequence(value.chars())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automata::{Automata, Inspect};
+    use crate::BuildError;
+
+    #[test]
+    fn combinators() {
+        let digits = NFAutomata::class('0'..='9');
+        let number = NFAutomata::literal("-").optional()
+            .concat(digits.plus())
+            .alternate(NFAutomata::literal("nan"));
+
+        assert!(number.test("-12"));
+        assert!(number.test("7"));
+        assert!(number.test("nan"));
+        assert!(!number.test("-"));
+        assert!(!number.test("12a"));
+        assert!(NFAutomata::literal("ab").star().test("abab"));
+        assert!(NFAutomata::literal("ab").star().test(""));
+    }
+
+    #[test]
+    fn built_by_hand() {
+        let mut builder = NFABuilder::new();
+        let a = builder.add_state_with_id(10).unwrap();
+        let b = builder.add_state_with_id(4).unwrap();
+        let c = builder.add_state();
+        builder.add_transition(a, b, Symbol::Character('a')).unwrap();
+        builder.add_transition(b, b, Symbol::Character('a')).unwrap();
+        builder.add_transition(c, a, Symbol::Character('b')).unwrap();
+
+        assert_eq!(11, c);
+        assert_eq!(Err(BuildError::UnknownState(3)), builder.add_transition(a, 3, Symbol::Epsilon));
+        assert_eq!(Err(BuildError::DuplicateState(4)), builder.add_state_with_id(4));
+
+        let automata = builder.into_automata([a, c], [b]).unwrap();
+        assert_eq!(3, automata.state_count());
+        assert_eq!(vec![4, 10, 11], automata.states());
+        assert_eq!(vec![10, 11], automata.start_states());
+        assert_eq!(vec![(10, Symbol::Character('a'), 4), (11, Symbol::Character('b'), 10)], automata.transitions()[1..]);
+        assert!(automata.test("aaa"));
+        assert!(automata.test("ba"));
+        assert!(!automata.test("b"));
+
+        // the skipped ids are kept when the automata is combined or reversed
+        let reversed = automata.reverse();
+        assert_eq!(vec![4, 10, 11], reversed.states());
+        assert!(reversed.test("ab"));
+        assert!(reversed.reverse().is_isomorphic(&automata));
+        assert_eq!(vec![4, 10, 11, 12, 13], automata.alternate(NFAutomata::literal("c")).states());
+    }
+
+    #[test]
+    fn over_tokens() {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        enum Token { Number, Identifier, Plus, Times }
+        use Token::*;
+
+        let operand = || NFAutomata::class([Number, Identifier]);
+        let operator = NFAutomata::class([Plus, Times]);
+        let expression = operand().concat(operator.concat(operand()).star());
+
+        assert!(expression.test_symbols([Number, Plus, Identifier, Times, Number]));
+        assert!(!expression.test_symbols([Number, Plus]));
+
+        let automata = expression.into_determinate(true);
+        assert_eq!(vec![Number, Identifier, Plus, Times], automata.alphabet());
+        assert!(automata.test_symbols([Identifier]));
+        assert!(!automata.test_symbols([Plus, Number]));
+        assert!(automata.complement().test_symbols([Number, Number]));
+    }
+}
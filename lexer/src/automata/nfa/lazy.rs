@@ -0,0 +1,243 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use crate::Symbol;
+use super::super::{State, Automata};
+use super::super::dfa::DFAutomata;
+use super::automata::NFAutomata;
+use super::simulation::Simulation;
+
+const DEFAULT_CAPACITY: usize = 1024;
+// the amount of times the cache can be cleared while testing a string before giving up on it
+const MAX_CLEARS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    /// the DFA states currently cached
+    pub states: usize,
+    /// how many times the cache was full and got cleared
+    pub clears: usize,
+    /// how many strings were tested simulating the NFA because the cache kept being cleared
+    pub fallbacks: usize,
+}
+
+// the part of the DFA built so far. The states are sets of NFA states, the empty one being dead
+struct Cache {
+    states: Vec<Vec<State>>,
+    state_ids: HashMap<Vec<State>, State>,
+    accepting: Vec<bool>,
+    transitions: HashMap<(State, char), State>,
+    stats: CacheStats,
+}
+
+impl Cache {
+    fn clear(&mut self) {
+        self.states.clear();
+        self.state_ids.clear();
+        self.accepting.clear();
+        self.transitions.clear();
+        self.stats.clears += 1;
+    }
+
+    fn insert(&mut self, automata: &NFAutomata, state: Vec<State>) -> State {
+        if let Some(&id) = self.state_ids.get(&state) {
+            return id;
+        }
+
+        self.accepting.push(automata.accepts(&state));
+        self.state_ids.insert(state.clone(), self.states.len());
+        self.states.push(state);
+        self.states.len() - 1
+    }
+}
+
+/// A DFA built from an NFA while reading the input, so only the states the input reaches get
+/// determinized. At most `capacity` states are kept, when the cache thrashes the NFA is simulated
+/// instead.
+pub struct LazyDFA {
+    automata: NFAutomata,
+    capacity: usize,
+    cache: RefCell<Cache>,
+}
+
+impl LazyDFA {
+    pub fn new(automata: NFAutomata) -> LazyDFA {
+        LazyDFA::with_capacity(automata, DEFAULT_CAPACITY)
+    }
+
+    pub fn with_capacity(automata: NFAutomata, capacity: usize) -> LazyDFA {
+        let cache = Cache {
+            states: Vec::new(),
+            state_ids: HashMap::new(),
+            accepting: Vec::new(),
+            transitions: HashMap::new(),
+            stats: CacheStats::default(),
+        };
+
+        LazyDFA { automata, capacity: capacity.max(2), cache: RefCell::new(cache) }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let cache = self.cache.borrow();
+        CacheStats { states: cache.states.len(), ..cache.stats }
+    }
+
+    /// the part of the DFA currently cached, without the dead state
+    pub fn explored(&self) -> DFAutomata {
+        let cache = self.cache.borrow();
+        // the cache may have been cleared since the start state was added, or never used at all. Then
+        // the start state is only added to the explored automata, so looking at it doesn't fill the cache
+        let start_closure = self.automata.start_closure();
+        let start_state = cache.state_ids.get(&start_closure).copied();
+
+        // the start state comes first
+        let cache_ids: Vec<State> = (0..cache.states.len())
+            .filter(|&id| Some(id) != start_state && !cache.states[id].is_empty())
+            .collect();
+
+        let mut new_ids = vec![None; cache.states.len()];
+        if let Some(start_state) = start_state {
+            new_ids[start_state] = Some(0);
+        }
+        cache_ids.iter().enumerate().for_each(|(new_id, &id)| new_ids[id] = Some(new_id + 1));
+
+        let transitions = cache.transitions.iter()
+            .filter_map(|(&(from, c), &to)| Some(((new_ids[from]?, c), new_ids[to]?)))
+            .collect();
+        let acceptance_states = std::iter::once(self.automata.accepts(&start_closure))
+            .chain(cache_ids.iter().map(|&id| cache.accepting[id]))
+            .enumerate()
+            .filter(|(_, accepting)| *accepting)
+            .map(|(new_id, _)| new_id)
+            .collect();
+        let subsets = std::iter::once(start_closure)
+            .chain(cache_ids.iter().map(|&id| cache.states[id].clone()))
+            .collect();
+
+        DFAutomata::new(transitions, acceptance_states, cache_ids.len())
+            .with_subsets(subsets)
+            .canonical()
+    }
+
+    fn next_state(&self, cache: &mut Cache, state: State, c: char) -> State {
+        if let Some(&to) = cache.transitions.get(&(state, c)) {
+            return to;
+        }
+
+        let new_state = self.automata.epsilon_closure(&self.automata.movement(&cache.states[state], &Symbol::Character(c)));
+        let to = cache.insert(&self.automata, new_state);
+        cache.transitions.insert((state, c), to);
+        to
+    }
+}
+
+impl Automata for LazyDFA {
+    fn test(&self, input: &str) -> bool {
+        let mut cache = self.cache.borrow_mut();
+        let mut clears = 0;
+
+        let start_closure = self.automata.start_closure();
+        if cache.states.len() >= self.capacity && !cache.state_ids.contains_key(&start_closure) {
+            cache.clear();
+            clears += 1;
+        }
+        let mut state = cache.insert(&self.automata, start_closure);
+
+        for (i, c) in input.char_indices() {
+            if cache.states.len() >= self.capacity && !cache.transitions.contains_key(&(state, c)) {
+                if clears == MAX_CLEARS {
+                    cache.stats.fallbacks += 1;
+                    let mut simulation = Simulation::starting_at(&self.automata, cache.states[state].iter().copied());
+                    for c in input[i..].chars() {
+                        simulation.step(&c);
+                    }
+                    return simulation.is_accepting();
+                }
+
+                // keep the current state so the search can go on
+                let current = cache.states[state].clone();
+                cache.clear();
+                clears += 1;
+                state = cache.insert(&self.automata, current);
+            }
+
+            state = self.next_state(&mut cache, state, c);
+            if cache.states[state].is_empty() {
+                return false;
+            }
+        }
+
+        cache.accepting[state]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automata::Inspect;
+
+    // the DFA of this expression has more than 2^20 states
+    fn exponential_expression() -> String {
+        format!("(a|b)*a{}", "(a|b)".repeat(20))
+    }
+
+    #[test]
+    fn lazy_dfa() {
+        let expression = exponential_expression();
+        let automata = LazyDFA::new(NFAutomata::try_from(expression.as_str()).unwrap());
+        let input = "ab".repeat(10) + "a" + &"b".repeat(20);
+
+        assert!(automata.test(&input));
+        assert!(!automata.test(&"b".repeat(30)));
+        assert!(automata.stats().states <= input.len() + 2);
+        assert_eq!(0, automata.stats().clears);
+
+        let explored = automata.explored();
+        assert!(explored.test(&input));
+        assert!(explored.subsets().is_some());
+    }
+
+    #[test]
+    fn thrashing() {
+        let expression = exponential_expression();
+        let nfa = NFAutomata::try_from(expression.as_str()).unwrap();
+        let automata = LazyDFA::with_capacity(NFAutomata::try_from(expression.as_str()).unwrap(), 8);
+
+        for input in ["ab".repeat(20) + "a" + &"b".repeat(20), "aab".repeat(15), "a".repeat(45)] {
+            assert_eq!(nfa.test(&input), automata.test(&input));
+        }
+        assert!(automata.stats().clears >= MAX_CLEARS);
+        assert!(automata.stats().fallbacks > 0);
+        assert!(automata.stats().states <= 8);
+
+        // the cache was cleared, so the start state may have been dropped from it
+        let stats = automata.stats();
+        let explored = automata.explored();
+        assert_eq!(stats, automata.stats());
+        assert_eq!(nfa.start_closure(), explored.subsets().unwrap()[0]);
+        for input in ["ab".repeat(20) + "a" + &"b".repeat(20), "a".repeat(21), "b".repeat(21)] {
+            assert!(!explored.test(&input) || nfa.test(&input));
+        }
+    }
+
+    #[test]
+    fn bounded_cache() {
+        let expression = exponential_expression();
+        let automata = LazyDFA::with_capacity(NFAutomata::try_from(expression.as_str()).unwrap(), 2);
+
+        // every string leaves the cache full without the start state, which the next one needs
+        for input in ["a".repeat(10), "b".to_string(), "ba".repeat(5), String::new()] {
+            automata.test(&input);
+            assert!(automata.stats().states <= 2);
+        }
+    }
+
+    #[test]
+    fn nothing_explored() {
+        let nfa = NFAutomata::try_from(exponential_expression().as_str()).unwrap();
+        let explored = LazyDFA::new(NFAutomata::try_from(exponential_expression().as_str()).unwrap()).explored();
+
+        assert_eq!(1, explored.state_count());
+        assert_eq!(vec![nfa.start_closure()], explored.subsets().unwrap());
+        assert!(!explored.test("a"));
+    }
+}
@@ -1,6 +1,11 @@
 mod automata;
 mod builder;
 mod visualizer;
+mod combinators;
+mod simulation;
+mod lazy;
 
 pub use automata::NFAutomata;
 pub use visualizer::NFAVisualizer;
+pub use builder::NFABuilder;
+pub use lazy::{LazyDFA, CacheStats};
@@ -0,0 +1,134 @@
+use crate::{Letter, Symbol};
+use super::super::State;
+use super::automata::NFAutomata;
+
+// A set of states with constant time insertion, lookup and clearing, as in Thompson's and Pike's
+// virtual machines. Nothing is allocated after creating it.
+struct SparseSet {
+    dense: Vec<State>,
+    // the position of each state in `dense`, only meaningful for the states in the set
+    sparse: Vec<usize>,
+}
+
+impl SparseSet {
+    fn new(capacity: usize) -> SparseSet {
+        SparseSet { dense: Vec::with_capacity(capacity), sparse: vec![0; capacity] }
+    }
+
+    fn contains(&self, state: State) -> bool {
+        let index = self.sparse[state];
+        index < self.dense.len() && self.dense[index] == state
+    }
+
+    fn insert(&mut self, state: State) -> bool {
+        if self.contains(state) {
+            return false;
+        }
+
+        self.sparse[state] = self.dense.len();
+        self.dense.push(state);
+        true
+    }
+
+    fn clear(&mut self) {
+        self.dense.clear();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+}
+
+// Runs the automata over the input one char at a time. Each step visits every state and transition
+// at most once, so matching takes O(n·m).
+pub(super) struct Simulation<'a, S> {
+    automata: &'a NFAutomata<S>,
+    accepting: Vec<bool>,
+    current: SparseSet,
+    next: SparseSet,
+    stack: Vec<State>,
+}
+
+impl<'a, S: Letter> Simulation<'a, S> {
+    pub(super) fn new(automata: &'a NFAutomata<S>) -> Simulation<'a, S> {
+        Simulation::starting_at(automata, automata.start_states.iter().copied())
+    }
+
+    // continues a simulation that reached these states
+    pub(super) fn starting_at(automata: &'a NFAutomata<S>, states: impl IntoIterator<Item=State>) -> Simulation<'a, S> {
+        let state_count = automata.id_bound();
+        let mut accepting = vec![false; state_count];
+        automata.acceptance_states.iter().for_each(|&state| accepting[state] = true);
+
+        let mut simulation = Simulation {
+            automata,
+            accepting,
+            current: SparseSet::new(state_count),
+            next: SparseSet::new(state_count),
+            stack: Vec::with_capacity(state_count),
+        };
+
+        for state in states {
+            Self::add_closure(automata, &mut simulation.current, &mut simulation.stack, state);
+        }
+        simulation
+    }
+
+    pub(super) fn step(&mut self, c: &S) {
+        self.next.clear();
+
+        for &from in &self.current.dense {
+            for (symbol, to) in &self.automata.transitions[from] {
+                if matches!(symbol, Symbol::Character(x) if x == c) {
+                    Self::add_closure(self.automata, &mut self.next, &mut self.stack, *to);
+                }
+            }
+        }
+
+        std::mem::swap(&mut self.current, &mut self.next);
+    }
+
+    pub(super) fn is_dead(&self) -> bool {
+        self.current.is_empty()
+    }
+
+    pub(super) fn is_accepting(&self) -> bool {
+        self.current.dense.iter().any(|&state| self.accepting[state])
+    }
+
+    // adds the state and everything reachable from it through ε transitions
+    fn add_closure(automata: &NFAutomata<S>, set: &mut SparseSet, stack: &mut Vec<State>, state: State) {
+        if !set.insert(state) {
+            return;
+        }
+
+        stack.push(state);
+        while let Some(state) = stack.pop() {
+            for (symbol, to) in &automata.transitions[state] {
+                if *symbol == Symbol::Epsilon && set.insert(*to) {
+                    stack.push(*to);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simulation() {
+        let automata = NFAutomata::try_from("(a|b)*abb").unwrap();
+        let mut simulation = Simulation::new(&automata);
+
+        for c in "aab".chars() {
+            simulation.step(&c);
+            assert!(!simulation.is_accepting());
+        }
+        simulation.step(&'b');
+        assert!(simulation.is_accepting());
+        simulation.step(&'c');
+        assert!(simulation.is_dead());
+    }
+}
@@ -1,52 +1,65 @@
-use std::collections::{HashMap, HashSet};
-use super::automata::{NFAutomata};
-use super::super::State;
-use crate::{Symbol, MermaidGraph};
+use std::io;
+use std::fmt::Display;
+use super::super::{Inspect, State, TraceStep};
+use crate::{Letter, Symbol, MermaidGraph};
 
-pub struct NFAVisualizer {
+pub struct NFAVisualizer<S = char> {
     mermaid: String,
+    // the transitions in the order they were drawn, mermaid identifies links by that order
+    edges: Vec<(State, Symbol<S>, State)>,
 }
 
-impl NFAVisualizer {
-   pub fn new(automata: &NFAutomata) -> NFAVisualizer {
-       let mut visualizer = NFAVisualizer { mermaid: String::new() };
-       visualizer.add_descriptions(automata.acceptance_state);
-       visualizer.add_transitions(&automata.transitions);
+impl<S: Letter + Display> NFAVisualizer<S> {
+   /// draws any automata, deterministic or not
+   pub fn new(automata: &impl Inspect<S>) -> NFAVisualizer<S> {
+       let mut visualizer = NFAVisualizer { mermaid: String::new(), edges: Vec::new() };
+       visualizer.add_descriptions(automata);
+       automata.transitions().into_iter().for_each(|(from, symbol, to)| visualizer.add_transition(from, to, symbol));
+       // drawn after the transitions so they don't change the order of their links
+       visualizer.add_start_arrows(automata.start_states());
 
        visualizer
    }
 
-    fn add_descriptions(&mut self, last_id: State) {
-        (0..last_id).for_each(|id| self.mermaid += &format!("\n        {id}(({id}))"));
-        self.mermaid += &format!("\n        {last_id}((({last_id})))");
+    fn add_descriptions(&mut self, automata: &impl Inspect<S>) {
+        for id in automata.states() {
+            if automata.is_accepting(id) {
+                self.mermaid += &format!("\n        {id}((({id})))");
+            } else {
+                self.mermaid += &format!("\n        {id}(({id}))");
+            }
+        }
     }
 
-    fn add_transition(&mut self, from: State, to: State, symbol: Symbol) {
-        match symbol {
-            Symbol::Character(x) => self.mermaid += &format!("\n        {from} -->|\"{x}\"| {to}"),
-            Symbol::Epsilon => {
-                if from != to {
-                    self.mermaid += &format!("\n        {from} -->|ε| {to}")
-                }
-            },
+    fn add_start_arrows(&mut self, start_states: Vec<State>) {
+        for id in start_states {
+            self.mermaid += &format!("\n        start{id}[\" \"] --> {id}");
+            self.mermaid += &format!("\n        style start{id} fill:none,stroke:none");
         }
     }
 
-    fn add_transitions(&mut self, transitions: &HashMap<(State, Symbol), HashSet<State>>) {
-        for (key, to) in transitions.iter() {
-            let (from, symbol) = key;
-
-            to.iter()
-                .for_each(|state| self.add_transition(*from, *state, *symbol));
+    fn add_transition(&mut self, from: State, to: State, symbol: Symbol<S>) {
+        match &symbol {
+            Symbol::Character(x) => self.mermaid += &format!("\n        {from} -->|\"{x}\"| {to}"),
+            Symbol::Epsilon => self.mermaid += &format!("\n        {from} -->|ε| {to}"),
         }
+        self.edges.push((from, symbol, to));
     }
 
-    pub fn show(&self, path: &str) -> String {
+    pub fn show(&self, path: &str) -> io::Result<String> {
         self.generate_and_open_graph(path)
     }
 }
 
-impl MermaidGraph for NFAVisualizer {
+impl NFAVisualizer {
+    pub fn show_trace(&self, trace: &[TraceStep], path: &str) -> io::Result<String> {
+        self.generate_and_open_trace(path, trace, |from, symbol, to|
+            self.edges.iter().position(|edge| *edge == (from, symbol, to))
+        )
+    }
+}
+
+impl<S> MermaidGraph for NFAVisualizer<S> {
     fn header(&self) -> &'static str {
         "graph LR"
     }
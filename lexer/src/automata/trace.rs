@@ -0,0 +1,42 @@
+use crate::Symbol;
+use super::State;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    /// the char read on this step. The first step has none, it only shows the initial states
+    pub input: Option<char>,
+    pub active_states: Vec<State>,
+    pub transitions: Vec<(State, Symbol, State)>,
+}
+
+impl TraceStep {
+    pub(crate) fn caption(&self, index: usize) -> String {
+        let action = match self.input {
+            Some(c) => format!("read `{c}`"),
+            None => "initial states".to_string(),
+        };
+
+        if self.active_states.is_empty() {
+            format!("step {index}: {action}, no active states left")
+        } else {
+            format!("step {index}: {action}")
+        }
+    }
+
+    /// mermaid styles highlighting the active states and the transitions taken on this step
+    pub(crate) fn highlight(&self, edge_index: impl Fn(State, Symbol, State) -> Option<usize>) -> String {
+        let mut styles = String::new();
+
+        for state in &self.active_states {
+            styles += &format!("\n        style {state} fill:#f96,stroke:#333,stroke-width:3px");
+        }
+
+        for &(from, symbol, to) in &self.transitions {
+            if let Some(index) = edge_index(from, symbol, to) {
+                styles += &format!("\n        linkStyle {index} stroke:#f00,stroke-width:3px");
+            }
+        }
+
+        styles
+    }
+}
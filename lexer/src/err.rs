@@ -8,4 +8,18 @@ pub enum LexError {
     MissingClosingParenthesis(usize, String),
     #[error("expected an argument at position {0} for the expression `{1}`")]
     MissingArgument(usize, String),
-}
\ No newline at end of file
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    #[error("the state {0} does not exist")]
+    UnknownState(usize),
+    #[error("the state {0} already exists")]
+    DuplicateState(usize),
+    #[error("the DFA has more than {0} states")]
+    TooManyDFAStates(usize),
+    #[error("the NFA has more than {0} states")]
+    TooManyNFAStates(usize),
+    #[error("the automata has more than {0} transitions")]
+    TooManyTransitions(usize),
+}
@@ -7,7 +7,8 @@ mod symbols;
 mod operator;
 mod err;
 
-use symbols::Symbol;
+pub use symbols::{Symbol, Letter};
 use operator::{UnaryOperator, BinaryOperator, Operator};
 use mermaid_graph::MermaidGraph;
-pub use err::LexError;
+pub use err::{LexError, BuildError};
+pub use visitor::{Visitable, Visitor, VisitorMut};
@@ -1,5 +1,13 @@
 use std::process::Command;
-use std::io::Write;
+use std::io::{self, Write};
+use crate::Symbol;
+use crate::automata::TraceStep;
+
+/// a set of states or positions the way the textbook writes them, like `{1,2,3}`
+pub(crate) fn format_set(values: &[usize]) -> String {
+    let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+    format!("{{{}}}", values.join(","))
+}
 
 pub trait MermaidGraph {
     fn generate_html(diagram_header: &str, diagram_content: &str) -> String {
@@ -17,41 +25,111 @@ pub trait MermaidGraph {
         "#)
     }
 
-    fn write_to_file(file: &str, content: &str) {
+    fn generate_animation_html(diagram_header: &str, frames: &[(String, String)]) -> String {
+        let captions: Vec<String> = frames.iter()
+            .map(|(caption, _)| Self::to_js_string(caption))
+            .collect();
+        let diagrams: Vec<String> = frames.iter()
+            .map(|(_, content)| Self::to_js_string(&format!("{diagram_header}{content}")))
+            .collect();
+        let captions = captions.join(",");
+        let diagrams = diagrams.join(",");
+
+        format!(r#"
+<!DOCTYPE html>
+<html lang="en"><head><meta charset="utf-8" /></head>
+  <body>
+    <div>
+      <button onclick="show(current - 1)">previous</button>
+      <button onclick="show(current + 1)">next</button>
+      <span id="caption"></span>
+    </div>
+    <div id="diagram"></div>
+    <script src="https://cdn.jsdelivr.net/npm/mermaid/dist/mermaid.min.js"></script>
+    <script>
+      const captions = [{captions}];
+      const diagrams = [{diagrams}];
+      let current = 0;
+
+      async function show(step) {{
+        if (step < 0 || step >= diagrams.length) return;
+        current = step;
+        const {{ svg }} = await mermaid.render('frame' + step, diagrams[step]);
+        document.getElementById('diagram').innerHTML = svg;
+        document.getElementById('caption').textContent = captions[step];
+      }}
+
+      mermaid.initialize({{startOnLoad:false, theme:'forest',themeVariables:{{edgeLabelBackground: '#FFFFFF'}}}});
+      show(0);
+    </script>
+  </body>
+</html>
+        "#)
+    }
+
+    fn to_js_string(value: &str) -> String {
+        let escaped = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+            .replace('<', "\\u003c");
+        format!("\"{escaped}\"")
+    }
+
+    fn write_to_file(file: &str, content: &str) -> io::Result<()> {
         let mut f = std::fs::OpenOptions::new()
             .create(true)
             .truncate(true)
             .write(true)
-            .open(file)
-            .unwrap();
-        f.write_all(content.as_bytes()).unwrap();
-        f.flush().unwrap();
+            .open(file)?;
+        f.write_all(content.as_bytes())?;
+        f.flush()
     }
 
-    fn open_file(file: &str) {
-        if cfg!(unix) {
+    fn open_file(file: &str) -> io::Result<()> {
+        let viewer = if cfg!(unix) {
             Command::new("open")
                 .arg(file)
                 .spawn()
-                .unwrap();
         } else {
             Command::new("cmd")
                 .arg("/c")
                 .arg("start")
                 .arg(file)
                 .spawn()
-                .unwrap();
-        }
+        };
+
+        viewer.map(|_| ())
     }
 
     fn header(&self) -> &'static str;
 
-    fn generate_and_open_graph(&self, file: &str) -> String {
+    /// writes the page of the graph and opens it, failing if either can't be done
+    fn generate_and_open_graph(&self, file: &str) -> io::Result<String> {
         let html = Self::generate_html(self.header(), self.get_mermaid_content());
-        Self::write_to_file(file, &html);
-        Self::open_file(file);
+        Self::write_to_file(file, &html)?;
+        Self::open_file(file)?;
+
+        Ok(html)
+    }
+
+    fn generate_and_open_animation(&self, file: &str, frames: &[(String, String)]) -> io::Result<String> {
+        let html = Self::generate_animation_html(self.header(), frames);
+        Self::write_to_file(file, &html)?;
+        Self::open_file(file)?;
+
+        Ok(html)
+    }
+
+    /// animates the trace over the graph, highlighting on every step the active states and the
+    /// transitions taken, found by their position among the drawn links
+    fn generate_and_open_trace(&self, file: &str, trace: &[TraceStep], edge_index: impl Fn(usize, Symbol, usize) -> Option<usize>) -> io::Result<String> {
+        let frames: Vec<(String, String)> = trace.iter()
+            .enumerate()
+            .map(|(i, step)| (step.caption(i), format!("{}{}", self.get_mermaid_content(), step.highlight(&edge_index))))
+            .collect();
 
-        html
+        self.generate_and_open_animation(file, &frames)
     }
 
     fn get_mermaid_content(&self) -> &str;
@@ -1,5 +1,13 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum Symbol {
+use std::hash::Hash;
+
+/// What the automata read, `char` unless they are built over some other alphabet such as tokens
+/// or bytes.
+pub trait Letter: Eq + Hash + Ord + Clone {}
+
+impl<T: Eq + Hash + Ord + Clone> Letter for T {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Symbol<S = char> {
     Epsilon,
-    Character(char)
+    Character(S)
 }
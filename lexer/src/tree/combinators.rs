@@ -0,0 +1,99 @@
+use std::ops::{Add, BitOr};
+use crate::{BinaryOperator, Symbol, UnaryOperator};
+use super::LexTree;
+
+// builds trees in code, the same ones the parser would build, without having to escape the
+// operators in the symbols
+
+/// matches the chars in this order, or only the empty string when there are none
+pub fn lit(value: &str) -> LexTree {
+    value.chars()
+        .map(LexTree::symbol)
+        .reduce(|left, right| left + right)
+        .unwrap_or_default()
+}
+
+/// matches any single char of the class
+///
+/// # Panics
+/// if the class is empty, as there is no tree matching nothing
+pub fn class(chars: impl IntoIterator<Item=char>) -> LexTree {
+    chars.into_iter()
+        .map(LexTree::symbol)
+        .reduce(|left, right| left | right)
+        .expect("a class needs at least one char")
+}
+
+impl LexTree {
+    fn symbol(c: char) -> LexTree {
+        LexTree::Leaf { value: Symbol::Character(c) }
+    }
+
+    fn binary(self, value: BinaryOperator, other: LexTree) -> LexTree {
+        LexTree::Binary { value, left_child: self.into(), right_child: other.into() }
+    }
+
+    fn unary(self, value: UnaryOperator) -> LexTree {
+        LexTree::Unary { value, child: self.into() }
+    }
+
+    pub fn star(self) -> LexTree {
+        self.unary(UnaryOperator::Kleene)
+    }
+
+    pub fn plus(self) -> LexTree {
+        self.unary(UnaryOperator::Many)
+    }
+
+    pub fn opt(self) -> LexTree {
+        self.unary(UnaryOperator::Maybe)
+    }
+}
+
+/// concatenation
+impl Add for LexTree {
+    type Output = LexTree;
+
+    fn add(self, other: LexTree) -> LexTree {
+        self.binary(BinaryOperator::Concat, other)
+    }
+}
+
+/// alternation
+impl BitOr for LexTree {
+    type Output = LexTree;
+
+    fn bitor(self, other: LexTree) -> LexTree {
+        self.binary(BinaryOperator::Or, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automata::Automata;
+    use crate::automata::dfa::DFAutomata;
+
+    #[test]
+    fn same_as_parsed() {
+        let cases = [
+            ((lit("a").star() | lit("b").star()) + lit("c"), "(a*|b*)c"),
+            (lit("ab").plus() + class('0'..='2').opt(), "(ab)+(0|1|2)?"),
+            (lit("a") | lit("") | lit("bc"), "a|ε|bc"),
+        ];
+
+        for (tree, expression) in cases {
+            assert_eq!(LexTree::try_from(expression).unwrap(), tree);
+        }
+    }
+
+    #[test]
+    fn no_escaping() {
+        let tree = lit("(a|b)*") + class(['\\', '?']).star();
+        let automata = DFAutomata::from(&tree);
+
+        assert_eq!(LexTree::try_from("\\(a\\|b\\)\\*(\\\\|\\?)*").unwrap(), tree);
+        assert!(automata.test("(a|b)*\\??"));
+        assert!(!automata.test("ab"));
+    }
+}
@@ -1,11 +1,11 @@
-use std::fmt::Debug;
-use super::Visitable;
+use std::fmt::{Debug, Formatter};
+use super::{Visitable, Visitor};
+use crate::visitor::DebugWriter;
 use crate::{UnaryOperator, BinaryOperator, Operator, LexError};
 use crate::Symbol;
 use super::tokenize::{to_postfix, tokenize_regular_expression, LexToken};
 
 
-#[derive(Debug, PartialEq, Eq)]
 pub enum LexTree {
     Binary { value: BinaryOperator, left_child: Box<LexTree>, right_child: Box<LexTree> },
     Unary { value: UnaryOperator, child: Box<LexTree> },
@@ -13,45 +13,137 @@ pub enum LexTree {
 }
 
 impl LexTree {
-    fn from_reference(stack: &mut Vec<LexToken>) -> LexTree {
-        let result = match stack.pop().expect("expected more tokens on the stack") {
-            LexToken::Symbol(value) =>
-                LexTree::Leaf { value },
-
-            LexToken::Operator(value) => {
-                match value {
-                    Operator::Binary(value) =>
-                        LexTree::Binary {
-                            value,
-                            // Because of postfix, the first pop would return the right child,
-                            right_child: LexTree::from_reference(stack).into(),
-                            left_child: LexTree::from_reference(stack).into(),
-                        },
-
-                    Operator::Unary(value) =>
-                        LexTree::Unary {
-                            value,
-                            child: LexTree::from_reference(stack).into(),
-                        },
-                    Operator::OpenParenthesis | Operator::CloseParenthesis =>
-                        panic!("there shouldn't be any parenthesis in a postfix expression"),
-
-                }
+    // builds the tree bottom up with a stack of the subtrees so far, so it works for trees of any
+    // depth
+    fn from_postfix(tokens: Vec<LexToken>) -> LexTree {
+        let mut subtrees: Vec<LexTree> = Vec::new();
+        let pop = |subtrees: &mut Vec<LexTree>| subtrees.pop().expect("expected more tokens on the stack");
+
+        for token in tokens {
+            let subtree = match token {
+                LexToken::Symbol(value) =>
+                    LexTree::Leaf { value },
+
+                LexToken::Operator(Operator::Binary(value)) => {
+                    // because of postfix, the right child is on top
+                    let right_child = pop(&mut subtrees).into();
+                    let left_child = pop(&mut subtrees).into();
+                    LexTree::Binary { value, left_child, right_child }
+                },
+
+                LexToken::Operator(Operator::Unary(value)) =>
+                    LexTree::Unary { value, child: pop(&mut subtrees).into() },
+
+                LexToken::Operator(Operator::OpenParenthesis | Operator::CloseParenthesis) =>
+                    panic!("there shouldn't be any parenthesis in a postfix expression"),
+            };
+            subtrees.push(subtree);
+        }
+
+        pop(&mut subtrees)
+    }
+}
+
+// the default drop would recurse as deep as the tree, so the subtrees are detached and dropped one
+// at a time
+impl Drop for LexTree {
+    fn drop(&mut self) {
+        let mut pending: Vec<LexTree> = self.children_mut().into_iter().map(std::mem::take).collect();
+        while let Some(mut node) = pending.pop() {
+            pending.extend(node.children_mut().into_iter().map(std::mem::take));
+        }
+    }
+}
+
+// like drop, the derived comparison would recurse as deep as the trees, so the pairs of nodes left
+// to compare are kept on a stack
+impl PartialEq for LexTree {
+    fn eq(&self, other: &Self) -> bool {
+        let mut pending = vec![(self, other)];
+
+        while let Some((left, right)) = pending.pop() {
+            let same_node = match (left, right) {
+                (LexTree::Binary { value: left, .. }, LexTree::Binary { value: right, .. }) => left == right,
+                (LexTree::Unary { value: left, .. }, LexTree::Unary { value: right, .. }) => left == right,
+                (LexTree::Leaf { value: left }, LexTree::Leaf { value: right }) => left == right,
+                _ => false,
+            };
+            if !same_node {
+                return false;
             }
-        };
+            pending.extend(left.children().into_iter().zip(right.children()));
+        }
+
+        true
+    }
+}
+
+impl Eq for LexTree {}
+
+// written the way it would be derived, but through a visitor so it works for trees of any depth
+impl Debug for LexTree {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut writer = DebugWriter::new(f.alternate());
+        self.accept(&mut writer);
+        f.write_str(&writer.finish())
+    }
+}
+
+impl Visitor<LexTree> for DebugWriter {
+    type Value = ();
+
+    fn enter(&mut self, node: &LexTree) {
+        match node {
+            LexTree::Binary { value, .. } => self.open("Binary", &[("value", value)]),
+            LexTree::Unary { value, .. } => self.open("Unary", &[("value", value)]),
+            LexTree::Leaf { value } => self.open("Leaf", &[("value", value)]),
+        }
+    }
+
+    fn before_child(&mut self, parent: &LexTree, index: usize, _siblings: &[()]) {
+        match (parent, index) {
+            (LexTree::Binary { .. }, 0) => self.field("left_child"),
+            (LexTree::Binary { .. }, _) => self.field("right_child"),
+            _ => self.field("child"),
+        }
+    }
+
+    fn leave(&mut self, _node: &LexTree, _children: Vec<()>) {
+        self.close();
+    }
+}
 
-        result
+/// the empty string, which is what an empty expression parses to
+impl Default for LexTree {
+    fn default() -> Self {
+        LexTree::Leaf { value: Symbol::Epsilon }
     }
 }
 
-impl<T> Visitable<T> for LexTree {}
+impl Visitable for LexTree {
+    fn children(&self) -> Vec<&LexTree> {
+        match self {
+            LexTree::Binary { left_child, right_child, .. } => vec![left_child.as_ref(), right_child.as_ref()],
+            LexTree::Unary { child, .. } => vec![child.as_ref()],
+            LexTree::Leaf { .. } => Vec::new(),
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut LexTree> {
+        match self {
+            LexTree::Binary { left_child, right_child, .. } => vec![left_child.as_mut(), right_child.as_mut()],
+            LexTree::Unary { child, .. } => vec![child.as_mut()],
+            LexTree::Leaf { .. } => Vec::new(),
+        }
+    }
+}
 
 
 impl TryFrom<&str> for LexTree {
     type Error = LexError;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        Ok(LexTree::from_reference(&mut to_postfix(
+        Ok(LexTree::from_postfix(to_postfix(
             tokenize_regular_expression(value)?
         )))
     }
@@ -103,4 +195,56 @@ mod tests {
 
         assert_eq!(expected, LexTree::try_from("(a*|b*)c").unwrap())
     }
+
+    #[test]
+    fn deep_and_wide_trees() {
+        use crate::automata::Automata;
+        use crate::automata::dfa::{AnnotatedLexTree, DFAutomata};
+        use crate::automata::nfa::NFAutomata;
+        use crate::mermaid_graph::MermaidGraph;
+        use super::super::LexTreeVisualizer;
+
+        const SIZE: usize = 100_000;
+        let cases = [
+            // a concatenation and an alternation, whose trees lean to the left
+            ("ab".repeat(SIZE / 2), "ab".repeat(SIZE / 2)),
+            ("a|".repeat(SIZE - 1) + "b", "b".to_string()),
+            // nested parenthesis, whose tree leans to the right
+            ("(a".repeat(SIZE) + &")".repeat(SIZE), "a".repeat(SIZE)),
+            // a chain of unary operators
+            ("a".to_string() + &"?".repeat(SIZE), "a".to_string()),
+        ];
+
+        for (expression, input) in cases {
+            let tree = LexTree::try_from(expression.as_str()).unwrap();
+
+            assert!(NFAutomata::from(&tree).test(&input));
+            // minimizing such long chains of states is quadratic, which isn't what's tested here
+            assert!(DFAutomata::unoptimized_from(&tree).test(&input));
+            assert!(LexTreeVisualizer::new(&tree).get_mermaid_content().len() > SIZE);
+        }
+
+        // the firstpos of an alternation holds all of its chars, so only a deep tree is annotated
+        let tree = LexTree::try_from(("(a".repeat(SIZE) + &")".repeat(SIZE)).as_str()).unwrap();
+        let annotated = AnnotatedLexTree::from(&tree);
+        assert_eq!(vec![0], annotated.annotation.first_positions);
+        assert!(LexTreeVisualizer::annotated(&annotated).get_mermaid_content().len() > SIZE);
+        assert!(format!("{annotated:?}").len() > SIZE);
+        assert!(format!("{tree:?}").len() > SIZE);
+        assert_eq!(tree, LexTree::try_from(("(a".repeat(SIZE) + &")".repeat(SIZE)).as_str()).unwrap());
+    }
+
+    #[test]
+    fn debug() {
+        let tree = LexTree::try_from("a*|b").unwrap();
+
+        assert_eq!(
+            "Binary { value: Or, left_child: Unary { value: Kleene, child: Leaf { value: Character('a') } }, right_child: Leaf { value: Character('b') } }",
+            format!("{tree:?}")
+        );
+        assert_eq!(
+            "Unary {\n    value: Kleene,\n    child: Leaf {\n        value: Character(\n            'a',\n        ),\n    },\n}",
+            format!("{:#?}", LexTree::try_from("a*").unwrap())
+        );
+    }
 }
@@ -1,8 +1,10 @@
 mod lexing_tree;
 mod tokenize;
 mod visualizer;
+mod combinators;
 
 use super::visitor::{Visitable, Visitor};
 
 pub use lexing_tree::LexTree;
 pub use visualizer::LexTreeVisualizer;
+pub use combinators::{lit, class};
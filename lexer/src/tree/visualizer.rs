@@ -1,20 +1,56 @@
+use std::io;
 use super::{Visitable, Visitor};
 use crate::{UnaryOperator, BinaryOperator, Symbol, MermaidGraph};
+use crate::mermaid_graph::format_set;
+use crate::automata::dfa::{AnnotatedLexTree, NodeAnnotation};
 use super::LexTree;
 
 pub struct LexTreeVisualizer {
     last_id: usize,
     mermaid: String,
+    // the ids of the nodes whose children are being drawn
+    parents: Vec<usize>,
+    // the annotation and position of every node in pre order, only when drawing an annotated tree
+    annotations: Vec<(NodeAnnotation, Option<usize>)>,
 }
 
 impl LexTreeVisualizer {
     pub fn new(node: &LexTree) -> Self {
-        let mut visualizer = LexTreeVisualizer { last_id: 0, mermaid: String::new() };
-        visualizer.visit(node);
+        let mut visualizer = LexTreeVisualizer { last_id: 0, mermaid: String::new(), parents: Vec::new(), annotations: Vec::new() };
+        node.accept(&mut visualizer);
         visualizer
     }
 
+    /// draws the tree along with the nullable, firstpos and lastpos of every node
+    pub fn annotated(tree: &AnnotatedLexTree) -> Self {
+        let mut visualizer = LexTreeVisualizer { last_id: 0, mermaid: String::new(), parents: Vec::new(), annotations: Self::collect_annotations(tree) };
+        tree.node.accept(&mut visualizer);
+        visualizer
+    }
+
+    // the annotations in pre order, the same order the nodes are numbered in
+    fn collect_annotations(tree: &AnnotatedLexTree) -> Vec<(NodeAnnotation, Option<usize>)> {
+        let mut annotations = Vec::new();
+        let mut pending = vec![tree];
+        while let Some(tree) = pending.pop() {
+            annotations.push((tree.annotation.clone(), tree.position));
+            pending.extend(tree.children.iter().rev());
+        }
+        annotations
+    }
+
     fn add_description(&mut self, id: usize, description: &str, is_terminal: bool) {
+        let description = match self.annotations.get(id) {
+            Some((annotation, position)) => {
+                let position = position.map(|x| format!("<sub>{x}</sub>")).unwrap_or_default();
+                let nullable = if annotation.nullable { "nullable" } else { "not nullable" };
+                format!("{} {description}{position} {}<br/>{nullable}",
+                        format_set(&annotation.first_positions),
+                        format_set(&annotation.last_positions))
+            },
+            None => description.to_string(),
+        };
+
         self.mermaid += &format!("\n        {id}((\"{description}\")) ");
         if is_terminal {
             self.mermaid += &format!("\n        style {id} fill:#f9f ");
@@ -25,67 +61,58 @@ impl LexTreeVisualizer {
         self.mermaid += &format!("\n        {from} --> {to} ");
     }
 
-    fn visit_unary(&mut self, value: UnaryOperator, child: &LexTree) {
-        let id = self.last_id;
-
+    fn describe_unary(&mut self, id: usize, value: UnaryOperator) {
         let description = match value {
             UnaryOperator::Kleene => "*",
             UnaryOperator::Maybe => "?",
             UnaryOperator::Many => "+",
         };
         self.add_description(id, description, false);
-
-        // graph the children.
-        self.last_id += 1;
-        self.add_connection(id, self.last_id);
-        child.accept(self);
     }
 
-    fn visit_binary(&mut self, value: BinaryOperator, left_child: &LexTree, right_child: &LexTree) {
-        let id = self.last_id;
-
+    fn describe_binary(&mut self, id: usize, value: BinaryOperator) {
         let description = match value {
             BinaryOperator::Concat => ".",
             BinaryOperator::Or => "|",
         };
         self.add_description(id, description, false);
-
-        // graph the children.
-        self.last_id += 1;
-        self.add_connection(id, self.last_id);
-        left_child.accept(self);
-
-        self.last_id += 1;
-        self.add_connection(id, self.last_id);
-        right_child.accept(self);
     }
 
-    fn visit_leaf(&mut self, value: Symbol) {
+    fn describe_leaf(&mut self, id: usize, value: Symbol) {
         let description = match value {
             Symbol::Character(x) => x.to_string(),
             Symbol::Epsilon => "ε".to_string(),
         };
 
-        self.add_description(self.last_id, &description, matches!(value, Symbol::Character(_)));
+        self.add_description(id, &description, matches!(value, Symbol::Character(_)));
     }
 
-    pub fn show(&self, path: &str) -> String {
+    pub fn show(&self, path: &str) -> io::Result<String> {
         self.generate_and_open_graph(path)
     }
 }
 
+// numbers the nodes in pre order
 impl Visitor<LexTree> for LexTreeVisualizer {
-    fn visit(&mut self, node: &LexTree) {
-        match node {
-            LexTree::Unary { value, child } =>
-                self.visit_unary(*value, child),
+    type Value = ();
 
-            LexTree::Binary { value, left_child, right_child } =>
-                self.visit_binary(*value, left_child, right_child),
+    fn enter(&mut self, node: &LexTree) {
+        let id = self.last_id;
+        self.last_id += 1;
+        if let Some(&parent) = self.parents.last() {
+            self.add_connection(parent, id);
+        }
 
-            LexTree::Leaf { value } =>
-                self.visit_leaf(*value)
+        match node {
+            LexTree::Unary { value, .. } => self.describe_unary(id, *value),
+            LexTree::Binary { value, .. } => self.describe_binary(id, *value),
+            LexTree::Leaf { value } => self.describe_leaf(id, *value),
         }
+        self.parents.push(id);
+    }
+
+    fn leave(&mut self, _node: &LexTree, _children: Vec<()>) {
+        self.parents.pop();
     }
 }
 
@@ -1,10 +1,270 @@
-pub trait Visitable<T> {
-    fn accept(&self, visitor: &mut impl Visitor<Self, T>) where Self: Sized {
-        visitor.visit(self);
+use std::fmt::Debug;
+
+/// A tree whose nodes can be walked by visitors. The walks keep the nodes left to visit on a stack
+/// instead of recursing, so trees of any depth can be visited.
+pub trait Visitable: Sized {
+    fn children(&self) -> Vec<&Self>;
+
+    fn children_mut(&mut self) -> Vec<&mut Self>;
+
+    /// visits the nodes depth first, returning the value the visitor computed for this one
+    fn accept<V: Visitor<Self>>(&self, visitor: &mut V) -> V::Value {
+        // every node being visited, along with its children and the values of the visited ones
+        let mut pending = vec![(self, self.children(), Vec::new())];
+        visitor.enter(self);
+
+        loop {
+            let (node, children, values) = pending.last_mut().expect("the root is visited last");
+            let node: &Self = node;
+
+            if let Some(&child) = children.get(values.len()).filter(|_| !visitor.is_done()) {
+                visitor.before_child(node, values.len(), values);
+                visitor.enter(child);
+                pending.push((child, child.children(), Vec::new()));
+                continue;
+            }
+
+            let (node, _, values) = pending.pop().expect("the node was just looked at");
+            let value = visitor.leave(node, values);
+            match pending.last_mut() {
+                Some((_, _, values)) => values.push(value),
+                None => return value,
+            }
+        }
+    }
+
+    /// lets the visitor replace every node, children before their parent
+    fn accept_mut(&mut self, visitor: &mut impl VisitorMut<Self>) where Self: Default {
+        // the children are detached while they are visited, so the stack can own them
+        let detach = |mut node: Self| {
+            let children: Vec<Self> = node.children_mut().into_iter().map(std::mem::take).collect();
+            (node, children.into_iter(), Vec::new())
+        };
+        let mut pending = vec![detach(std::mem::take(self))];
+
+        loop {
+            let (_, children, _) = pending.last_mut().expect("the root is visited last");
+
+            if let Some(child) = children.next() {
+                pending.push(detach(child));
+                continue;
+            }
+
+            let (mut node, _, visited) = pending.pop().expect("the node was just looked at");
+            node.children_mut().into_iter().zip(visited).for_each(|(slot, child)| *slot = child);
+            visitor.visit_mut(&mut node);
+
+            match pending.last_mut() {
+                Some((_, _, visited)) => visited.push(node),
+                None => {
+                    *self = node;
+                    return;
+                },
+            }
+        }
     }
 }
 
+/// A pass over a tree. Every node is entered before its children are visited and left after,
+/// folding the values of its children into its own.
+pub trait Visitor<T: Visitable> {
+    type Value;
+
+    fn enter(&mut self, _node: &T) {}
+
+    /// called before visiting the child at the index, with the values of the children before it
+    fn before_child(&mut self, _parent: &T, _index: usize, _siblings: &[Self::Value]) {}
+
+    fn leave(&mut self, node: &T, children: Vec<Self::Value>) -> Self::Value;
+
+    /// whether the rest of the tree can be skipped. The nodes being visited are then left with the
+    /// values of the children visited so far
+    fn is_done(&self) -> bool {
+        false
+    }
+}
+
+/// A pass that may replace the nodes of a tree. The children of a node were already visited, and
+/// the node put in its place isn't visited again.
+pub trait VisitorMut<T: Visitable> {
+    fn visit_mut(&mut self, node: &mut T);
+}
+
+/// Writes a tree the way `#[derive(Debug)]` would, for the trees whose derived Debug would recurse
+/// as deep as them. Visitors open every node with the fields that aren't subtrees, name the field of
+/// each subtree before it is visited, and close the node when leaving it.
+pub(crate) struct DebugWriter {
+    output: String,
+    // whether to write it like `{:#?}`, one field per line
+    pretty: bool,
+    depth: usize,
+    // whether nothing was written yet inside the struct or list just opened
+    first: bool,
+}
+
+impl DebugWriter {
+    pub(crate) fn new(pretty: bool) -> DebugWriter {
+        DebugWriter { output: String::new(), pretty, depth: 0, first: true }
+    }
+
+    pub(crate) fn open(&mut self, name: &str, fields: &[(&str, &dyn Debug)]) {
+        self.output += name;
+        self.output += " {";
+        self.depth += 1;
+        self.first = true;
+
+        for (field, value) in fields {
+            self.field(field);
+            self.value(*value);
+        }
+    }
+
+    pub(crate) fn field(&mut self, name: &str) {
+        self.separate(" ");
+        self.output += name;
+        self.output += ": ";
+    }
+
+    pub(crate) fn close(&mut self) {
+        self.depth -= 1;
+        if self.pretty {
+            self.output += &format!(",\n{}}}", self.indent());
+        } else {
+            self.output += " }";
+        }
+    }
+
+    pub(crate) fn open_list(&mut self) {
+        self.output += "[";
+        self.depth += 1;
+        self.first = true;
+    }
+
+    /// called before writing each item of the list
+    pub(crate) fn item(&mut self) {
+        self.separate("");
+    }
 
-pub trait Visitor<T, E = ()> where T: Visitable<E> + Sized {
-    fn visit(&mut self, visitable: &T) -> E;
+    pub(crate) fn close_list(&mut self) {
+        self.depth -= 1;
+        if self.pretty && !self.first {
+            self.output += &format!(",\n{}]", self.indent());
+        } else {
+            self.output += "]";
+        }
+        self.first = false;
+    }
+
+    pub(crate) fn finish(self) -> String {
+        self.output
+    }
+
+    fn value(&mut self, value: &dyn Debug) {
+        if self.pretty {
+            let indent = format!("\n{}", self.indent());
+            self.output += &format!("{value:#?}").replace('\n', &indent);
+        } else {
+            self.output += &format!("{value:?}");
+        }
+    }
+
+    // goes to a new line when pretty, and otherwise separates the entries with commas
+    fn separate(&mut self, first_separator: &str) {
+        if self.pretty {
+            if !self.first {
+                self.output += ",";
+            }
+            self.output += &format!("\n{}", self.indent());
+        } else if self.first {
+            self.output += first_separator;
+        } else {
+            self.output += ", ";
+        }
+        self.first = false;
+    }
+
+    fn indent(&self) -> String {
+        "    ".repeat(self.depth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::{lit, LexTree};
+    use crate::UnaryOperator;
+
+    // the height of the tree, along with how many leaves it has
+    struct Height;
+
+    impl Visitor<LexTree> for Height {
+        type Value = (usize, usize);
+
+        fn leave(&mut self, _node: &LexTree, children: Vec<(usize, usize)>) -> (usize, usize) {
+            let height = children.iter().map(|(height, _)| height + 1).max().unwrap_or(0);
+            let leaves = children.iter().map(|(_, leaves)| leaves).sum::<usize>().max(1);
+            (height, leaves)
+        }
+    }
+
+    // how many nodes are entered until the first leaf is left
+    #[derive(Default)]
+    struct UntilFirstLeaf {
+        entered: usize,
+        found: bool,
+    }
+
+    impl Visitor<LexTree> for UntilFirstLeaf {
+        type Value = ();
+
+        fn enter(&mut self, _node: &LexTree) {
+            self.entered += 1;
+        }
+
+        fn leave(&mut self, node: &LexTree, _children: Vec<()>) {
+            self.found |= matches!(node, LexTree::Leaf { .. });
+        }
+
+        fn is_done(&self) -> bool {
+            self.found
+        }
+    }
+
+    // writes `x?` as `x|ε`
+    struct ExpandMaybe;
+
+    impl VisitorMut<LexTree> for ExpandMaybe {
+        fn visit_mut(&mut self, node: &mut LexTree) {
+            if let LexTree::Unary { value: UnaryOperator::Maybe, child } = node {
+                let child = std::mem::take(child.as_mut());
+                *node = child | LexTree::default();
+            }
+        }
+    }
+
+    #[test]
+    fn fold() {
+        assert_eq!((4, 4), LexTree::try_from("(a*|b)cd").unwrap().accept(&mut Height));
+
+        let deep = "(a".repeat(100_000) + &")".repeat(100_000);
+        let deep = LexTree::try_from(deep.as_str()).unwrap();
+        assert_eq!((99_999, 100_000), deep.accept(&mut Height));
+    }
+
+    #[test]
+    fn stop_early() {
+        let mut visitor = UntilFirstLeaf::default();
+        LexTree::try_from("ab".repeat(1000).as_str()).unwrap().accept(&mut visitor);
+
+        // the concatenations lean to the left, so the first leaf is the deepest one
+        assert_eq!(2000, visitor.entered);
+    }
+
+    #[test]
+    fn rewrite() {
+        let mut tree = lit("a").opt().opt() + lit("b").opt();
+        tree.accept_mut(&mut ExpandMaybe);
+
+        assert_eq!(LexTree::try_from("((a|ε)|ε)(b|ε)").unwrap(), tree);
+    }
 }
@@ -1,9 +1,10 @@
+use std::io;
 use clap::{Parser, ValueEnum};
-use lexer::automata::dfa::{DFAutomata, DFAVisualizer};
-use lexer::automata::nfa::{NFAutomata, NFAVisualizer};
+use lexer::automata::dfa::{DFAutomata, DFAVisualizer, MatchReport, RefinementVisualizer, AnnotatedLexTree, FollowPositions, MinimizationAlgorithm};
+use lexer::automata::nfa::{NFAutomata, NFAVisualizer, LazyDFA};
 use lexer::tree::{LexTree, LexTreeVisualizer};
-use lexer::automata::Automata;
-use lexer::LexError;
+use lexer::automata::{Automata, Limits};
+use lexer::{BuildError, LexError};
 
 #[derive(Parser)]
 #[command(author, version, about)]
@@ -16,6 +17,38 @@ struct Cli {
     /// the automata of tree to create from the input expression
     #[arg(short, long, value_enum, default_value_t = Mode::Nfa)]
     mode: Mode,
+    /// animate how the automata reads the string instead of just testing it
+    #[arg(short, long, requires = "string")]
+    trace: bool,
+    /// the algorithm used to show the minimization
+    #[arg(long, value_enum, default_value_t = Algorithm::Hopcroft)]
+    minimization: Algorithm,
+    /// the most states a DFA can have before giving up on building it
+    #[arg(long, default_value_t = Limits::default().dfa_states)]
+    max_dfa_states: usize,
+    /// the most states an NFA can have before giving up on building it
+    #[arg(long, default_value_t = Limits::default().nfa_states)]
+    max_nfa_states: usize,
+    /// the most transitions an automata can have before giving up on building it
+    #[arg(long, default_value_t = Limits::default().transitions)]
+    max_transitions: usize,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum Algorithm {
+    /// refine every partition until nothing changes
+    Moore,
+    /// refine partitions using a worklist of splitters
+    Hopcroft,
+}
+
+impl From<Algorithm> for MinimizationAlgorithm {
+    fn from(value: Algorithm) -> Self {
+        match value {
+            Algorithm::Moore => MinimizationAlgorithm::Moore,
+            Algorithm::Hopcroft => MinimizationAlgorithm::Hopcroft,
+        }
+    }
 }
 
 fn valid_expression(s: &str) -> Result<String, String> {
@@ -34,12 +67,82 @@ fn valid_expression(s: &str) -> Result<String, String> {
     }
 }
 
+// the columns a terminal takes to show the string, so the caret lands under the right character.
+// Combining marks take none, and wide east asian characters and emoji take two
+fn display_width(s: &str) -> usize {
+    s.chars()
+        .map(|c| match c as u32 {
+            0x0300..=0x036F | 0x1AB0..=0x1AFF | 0x1DC0..=0x1DFF | 0x200B..=0x200F | 0x20D0..=0x20FF |
+            0xFE00..=0xFE0F | 0xFE20..=0xFE2F => 0,
+            0x1100..=0x115F | 0x2E80..=0x303E | 0x3041..=0x33FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF |
+            0xA000..=0xA4CF | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF | 0xFE30..=0xFE4F | 0xFF00..=0xFF60 |
+            0xFFE0..=0xFFE6 | 0x1F300..=0x1F64F | 0x1F900..=0x1F9FF | 0x20000..=0x3FFFD => 2,
+            _ => 1,
+        })
+        .sum()
+}
+
+fn explain_rejection(s: &str, report: &MatchReport) -> String {
+    let consumed: String = s.chars().take(report.consumed).collect();
+    let spaces = " ".repeat(display_width(&consumed));
+    let reason = if report.consumed < s.chars().count() {
+        "unexpected character"
+    } else {
+        "unexpected end of string"
+    };
+    let mut explanation = format!("\n\t{s}\n\t{spaces}↑\n\t{spaces}{reason}");
+
+    if !report.expected.is_empty() {
+        let expected: Vec<String> = report.expected.iter()
+            .map(|c| format!("`{c}`"))
+            .collect();
+        explanation += &format!("\n\texpected one of: {}", expected.join(", "));
+    }
+
+    match &report.completion {
+        Some(completion) if !completion.is_empty() =>
+            explanation += &format!("\n\tshortest completion: `{consumed}{completion}`"),
+        Some(_) => {},
+        None => explanation += "\n\tno string starting like this belongs to the language",
+    }
+
+    explanation
+}
+
+// the pages are written to `test.html` and opened, which may fail without being fatal
+fn report_page_error(shown: io::Result<String>) {
+    if let Err(err) = shown {
+        eprintln!("could not show `test.html`: {err}");
+    }
+}
+
+// stops the program when the automata is bigger than the limits
+fn within_limits<T>(automata: Result<T, BuildError>) -> T {
+    automata.unwrap_or_else(|err| {
+        eprintln!("{err}.");
+        match err {
+            BuildError::TooManyDFAStates(_) => {
+                eprintln!("\ttry the `nfa` or `lazy-dfa` modes, which never build the whole DFA, or raise the limit with");
+                eprintln!("\t--max-dfa-states");
+            },
+            BuildError::TooManyNFAStates(_) => eprintln!("\traise the limit with --max-nfa-states"),
+            BuildError::TooManyTransitions(_) => eprintln!("\traise the limit with --max-transitions"),
+            BuildError::UnknownState(_) | BuildError::DuplicateState(_) => {},
+        }
+        std::process::exit(1)
+    })
+}
+
 #[derive(Copy, Clone, ValueEnum)]
 enum Mode {
     /// LexTree
     Tree,
+    /// LexTree with the nullable, firstpos and lastpos of every node, along with its followpos table
+    AnnotatedTree,
     /// NDA built using Thompson
     Nfa,
+    /// DFA built from a Thompson NDA while reading the string, only determinizing the states it reaches
+    LazyDfa,
     /// minimized DFA built directly from re
     Dfa,
     /// DFA built directly from re
@@ -48,41 +151,107 @@ enum Mode {
     ThompsonDfa,
     /// DFA built from a Thompson NDA without minimizations
     ThompsonDfaUnoptimized,
+    /// how the states of the DFA built from a Thompson NDA get merged when minimizing it
+    Minimization,
+    /// DFA built directly from re, minimized by determinizing its reverse twice
+    Brzozowski,
+}
+
+fn build_dfa(mode: Mode, tree: &LexTree, limits: &Limits) -> Result<DFAutomata, BuildError> {
+    match mode {
+        Mode::Dfa => DFAutomata::build_with_limits(tree, true, limits),
+        Mode::DfaUnoptimized => DFAutomata::build_with_limits(tree, false, limits),
+        Mode::Brzozowski => DFAutomata::build_with_limits(tree, false, limits)?.brzozowski_with_limits(limits),
+        Mode::ThompsonDfa | Mode::Minimization => NFAutomata::build_with_limits(tree, limits)?.into_determinate_with_limits(true, limits),
+        Mode::ThompsonDfaUnoptimized => NFAutomata::build_with_limits(tree, limits)?.into_determinate_with_limits(false, limits),
+        Mode::Tree | Mode::AnnotatedTree | Mode::Nfa | Mode::LazyDfa => unreachable!("the mode doesn't build a DFA"),
+    }
 }
 
 fn main() {
     let cli = Cli::parse();
     let tree = LexTree::try_from(cli.expression.as_str()).unwrap(); // because of the validation this won't fail
+    let limits = Limits { dfa_states: cli.max_dfa_states, nfa_states: cli.max_nfa_states, transitions: cli.max_transitions };
+    let nfa = || within_limits(NFAutomata::build_with_limits(&tree, &limits));
+
+    if let (Some(s), true) = (&cli.string, cli.trace) {
+        fn show_dfa_trace(automata: &DFAutomata, s: &str) -> io::Result<String> {
+            DFAVisualizer::new(automata).show_trace(&automata.trace(s), "test.html")
+        }
 
-    if let Some(s) = &cli.string {
-        let automata: Box<dyn Automata> = match cli.mode {
-            Mode::Nfa => Box::new(NFAutomata::from(&tree)),
-            Mode::Dfa => Box::new(DFAutomata::from(&tree)),
-            Mode::DfaUnoptimized => Box::new(DFAutomata::unoptimized_from(&tree)),
-            Mode::ThompsonDfaUnoptimized => Box::new(NFAutomata::from(&tree).into_determinate(false)),
-            Mode::ThompsonDfa => Box::new(NFAutomata::from(&tree).into_determinate(true)),
-            Mode::Tree => {
+        let shown = match cli.mode {
+            Mode::Nfa => {
+                let automata = nfa();
+                NFAVisualizer::new(&automata).show_trace(&automata.trace(s), "test.html")
+            },
+            Mode::LazyDfa => {
+                let automata = LazyDFA::new(nfa());
+                automata.test(s);
+                show_dfa_trace(&automata.explored(), s)
+            },
+            Mode::Dfa | Mode::DfaUnoptimized | Mode::Brzozowski | Mode::ThompsonDfa | Mode::ThompsonDfaUnoptimized | Mode::Minimization =>
+                show_dfa_trace(&within_limits(build_dfa(cli.mode, &tree, &limits)), s),
+            Mode::Tree | Mode::AnnotatedTree => {
+                eprintln!("cannot trace a string on a tree.");
+                return; // early return
+            },
+        };
+        report_page_error(shown);
+    } else if let Some(s) = &cli.string {
+        // the DFA that was tested is kept to explain why it rejected the string
+        let (matches, dfa) = match cli.mode {
+            Mode::Nfa => (nfa().test(s), None),
+            Mode::LazyDfa => (LazyDFA::new(nfa()).test(s), None),
+            Mode::Dfa | Mode::DfaUnoptimized | Mode::Brzozowski | Mode::ThompsonDfa | Mode::ThompsonDfaUnoptimized | Mode::Minimization => {
+                let automata = within_limits(build_dfa(cli.mode, &tree, &limits));
+                (automata.test(s), Some(automata))
+            },
+            Mode::Tree | Mode::AnnotatedTree => {
                 eprintln!("cannot test a language against a tree.");
                 return; // early return
             },
         };
 
-        if automata.test(s) {
+        if matches {
             println!("the inputted string matches the language");
         } else {
             println!("the inputted string does not match the language");
+            // every automata accepts the same language, so when no DFA was built the minimized one can
+            // explain why, as long as it isn't too big to build
+            let automata = dfa.map(Ok).unwrap_or_else(|| DFAutomata::build_with_limits(&tree, true, &limits));
+            if let Ok(automata) = automata {
+                println!("{}", explain_rejection(s, &automata.match_report(s)));
+            }
         }
 
     } else {
-        match cli.mode {
+        fn show_dfa(automata: &DFAutomata) -> io::Result<String> {
+            if let Some(table) = DFAVisualizer::dstates_table(automata) {
+                println!("{table}");
+            }
+            DFAVisualizer::new(automata).show("test.html")
+        }
+
+        let shown = match cli.mode {
             Mode::Tree => LexTreeVisualizer::new(&tree).show("test.html"),
-            Mode::Dfa => DFAVisualizer::new(&DFAutomata::from(&tree)).show("test.html"),
-            Mode::DfaUnoptimized => DFAVisualizer::new(&DFAutomata::unoptimized_from(&tree)).show("test.html"),
-            Mode::Nfa => NFAVisualizer::new(&NFAutomata::from(&tree)).show("test.html"),
-            Mode::ThompsonDfa =>
-                DFAVisualizer::new(&NFAutomata::from(&tree).into_determinate(true)).show("test.html"),
-            Mode::ThompsonDfaUnoptimized =>
-                DFAVisualizer::new(&NFAutomata::from(&tree).into_determinate(false)).show("test.html"),
+            Mode::AnnotatedTree => {
+                println!("{}", FollowPositions::from(&tree));
+                LexTreeVisualizer::annotated(&AnnotatedLexTree::from(&tree)).show("test.html")
+            },
+            Mode::Dfa | Mode::DfaUnoptimized | Mode::Brzozowski | Mode::ThompsonDfa | Mode::ThompsonDfaUnoptimized =>
+                show_dfa(&within_limits(build_dfa(cli.mode, &tree, &limits))),
+            Mode::Nfa => NFAVisualizer::new(&nfa()).show("test.html"),
+            Mode::LazyDfa => {
+                eprintln!("the lazy DFA has no states until it reads a string.");
+                return; // early return
+            },
+            Mode::Minimization => {
+                let automata = within_limits(nfa().into_determinate_with_limits(false, &limits))
+                    .minimized_with_steps(cli.minimization.into());
+                let log = automata.minimization().expect("the automata was minimized");
+                RefinementVisualizer::new(log).show("test.html")
+            },
         };
+        report_page_error(shown);
     }
 }